@@ -0,0 +1,80 @@
+//! Per-window latency of the detectors, run natively with `cargo bench`. The numbers are only
+//! a guide to the relative cost of the detectors, as WASM in a browser is slower.
+
+#[path = "../tests/signals/mod.rs"]
+mod signals;
+
+use std::time::{Duration, Instant};
+
+use pitch_detection_wasm::{AutocorrelationDetector, McLeodDetector, PyinDetector, YinDetector};
+
+const SAMPLE_RATE: usize = 44_100;
+const WINDOW_SIZES: [usize; 3] = [1024, 2048, 4096];
+/// How long to spend measuring each detector and window size.
+const MEASUREMENT_TIME: Duration = Duration::from_millis(500);
+
+/// Run `detect` on windows of a vowel-like tone for `MEASUREMENT_TIME`, returning the average
+/// time per window.
+fn measure(window_size: usize, mut detect: impl FnMut(&[f32])) -> Duration {
+    let signal = signals::voice(220.0, 5.5, 50.0, 0.1, SAMPLE_RATE, window_size * 8);
+    let windows: Vec<&[f32]> = signal.chunks_exact(window_size).collect();
+
+    // Warm up, so the first measurement doesn't include page faults and the like.
+    detect(windows[0]);
+
+    let start = Instant::now();
+    let mut count = 0;
+    while start.elapsed() < MEASUREMENT_TIME {
+        detect(windows[count % windows.len()]);
+        count += 1;
+    }
+    start.elapsed() / count as u32
+}
+
+fn main() {
+    println!(
+        "{:<16} {:>8} {:>14} {:>12}",
+        "detector", "window", "per window", "real time"
+    );
+    for &size in WINDOW_SIZES.iter() {
+        let padding = size / 2;
+        let results = [
+            ("autocorrelation", {
+                let mut detector = AutocorrelationDetector::new(size, padding);
+                measure(size, |window| {
+                    detector.detect(window, SAMPLE_RATE, 0.01, 0.7);
+                })
+            }),
+            ("mcleod", {
+                let mut detector = McLeodDetector::new(size, padding);
+                measure(size, |window| {
+                    detector.detect(window, SAMPLE_RATE, 0.01, 0.7);
+                })
+            }),
+            ("yin", {
+                let mut detector = YinDetector::new(size, padding);
+                measure(size, |window| {
+                    detector.detect(window, SAMPLE_RATE, 0.01, 0.7);
+                })
+            }),
+            ("pyin", {
+                let mut detector = PyinDetector::new(size, padding);
+                measure(size, |window| {
+                    detector.detect(window, SAMPLE_RATE, 0.01, 0.7);
+                })
+            }),
+        ];
+
+        for (name, latency) in results.iter() {
+            // How much of the window's duration it takes to analyse it.
+            let window_duration = size as f64 / SAMPLE_RATE as f64;
+            println!(
+                "{:<16} {:>8} {:>11.1} µs {:>11.1}%",
+                name,
+                size,
+                latency.as_secs_f64() * 1e6,
+                100.0 * latency.as_secs_f64() / window_duration
+            );
+        }
+    }
+}
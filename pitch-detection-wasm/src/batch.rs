@@ -0,0 +1,97 @@
+//! Pitch analysis of a whole buffer in one call, e.g. a recorded performance or a song's vocal
+//! track, so that JS doesn't have to hand the audio over one window at a time.
+
+use wasm_bindgen::prelude::*;
+
+use crate::tracker::{PitchContour, PitchFrame};
+use crate::{new_detector, PitchAlgorithm};
+
+/// Detect the pitch of every window of `window_size` samples of `signal`, starting a new window
+/// every `hop_size` samples. Windows that would run past the end of the signal are skipped.
+/// Each window is analysed on its own; use a `PitchTracker` for a smoothed contour.
+#[wasm_bindgen]
+pub fn analyze_pitch(
+    signal: &[f32],
+    sample_rate: usize,
+    algorithm: PitchAlgorithm,
+    window_size: usize,
+    hop_size: usize,
+    power_threshold: f32,
+    clarity_threshold: f32,
+) -> PitchContour {
+    analyze(
+        signal,
+        sample_rate,
+        algorithm,
+        window_size,
+        hop_size,
+        power_threshold,
+        clarity_threshold,
+    )
+    .into_iter()
+    .collect()
+}
+
+pub fn analyze(
+    signal: &[f32],
+    sample_rate: usize,
+    algorithm: PitchAlgorithm,
+    window_size: usize,
+    hop_size: usize,
+    power_threshold: f32,
+    clarity_threshold: f32,
+) -> Vec<PitchFrame> {
+    if window_size == 0 || signal.len() < window_size {
+        return vec![];
+    }
+    let mut detector = new_detector(algorithm, window_size, window_size / 2);
+
+    (0..=signal.len() - window_size)
+        .step_by(hop_size.max(1))
+        .map(|start| {
+            let pitch = detector.get_pitch(
+                &signal[start..start + window_size],
+                sample_rate,
+                power_threshold,
+                clarity_threshold,
+            );
+            PitchFrame {
+                time: start as f64 / sample_rate as f64,
+                frequency: pitch.as_ref().map(|pitch| pitch.frequency),
+                clarity: pitch.map_or(0.0, |pitch| pitch.clarity),
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn contour_follows_a_change_of_note() {
+        // Half a second of 200 Hz followed by half a second of 300 Hz.
+        let signal: Vec<f32> = (0..8_000)
+            .map(|i| {
+                let frequency = if i < 4_000 { 200.0 } else { 300.0 };
+                0.5 * (std::f32::consts::TAU * frequency * i as f32 / 8_000.0).sin()
+            })
+            .collect();
+        let frames = analyze(&signal, 8_000, PitchAlgorithm::Yin, 512, 500, 0.1, 0.8);
+
+        assert_eq!(frames.len(), 15);
+        for frame in &frames {
+            assert_eq!(frame.time * 8_000.0 % 500.0, 0.0);
+            let window_end = frame.time + 512.0 / 8_000.0;
+            if window_end <= 0.5 {
+                assert!((frame.frequency.unwrap() - 200.0).abs() < 1.0);
+            } else if frame.time >= 0.5 {
+                assert!((frame.frequency.unwrap() - 300.0).abs() < 1.0);
+            }
+        }
+
+        let contour = analyze_pitch(&signal, 8_000, PitchAlgorithm::Yin, 512, 500, 0.1, 0.8);
+        assert_eq!(contour.len(), 15);
+        assert_eq!(contour.times()[1], 500.0 / 8_000.0);
+    }
+}
@@ -1,6 +1,20 @@
+mod batch;
+mod pyin;
+mod result;
+mod spectrogram;
+mod spectrum;
+mod stream;
 mod utils;
+mod vibrato;
 #[macro_use]
 mod log;
+mod multipitch;
+mod music;
+mod notes;
+mod onset;
+mod tracker;
+mod windower;
+mod yin;
 //use log::*;
 
 use wasm_bindgen::prelude::*;
@@ -9,6 +23,20 @@ use pitch_detection::detector::autocorrelation::AutocorrelationDetector as Autoc
 use pitch_detection::detector::internals::Pitch;
 use pitch_detection::detector::mcleod::McLeodDetector as McLeodDetectorInternal;
 use pitch_detection::detector::PitchDetector;
+use pyin::PyinDetector as PyinDetectorInternal;
+use yin::YinDetector as YinDetectorInternal;
+
+pub use batch::analyze_pitch;
+pub use multipitch::{MultiPitch, MultiPitchDetector};
+pub use music::{Mode, Temperament, TunedNote, Tuning};
+pub use notes::{Note, NoteSegmenter};
+pub use onset::OnsetDetector;
+pub use result::PitchResult;
+pub use spectrogram::{Spectrogram, SpectrogramAnalyzer};
+pub use spectrum::WindowFunction;
+pub use stream::StreamingDetector;
+pub use tracker::{PitchContour, PitchTracker};
+pub use vibrato::{analyze_vibrato, VibratoAnalysis};
 
 // When the `wee_alloc` feature is enabled, use `wee_alloc` as the global
 // allocator.
@@ -16,16 +44,51 @@ use pitch_detection::detector::PitchDetector;
 #[global_allocator]
 static ALLOC: wee_alloc::WeeAlloc = wee_alloc::WeeAlloc::INIT;
 
+/// The pitch detection algorithms, for types that create their own detector.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PitchAlgorithm {
+    Autocorrelation,
+    McLeod,
+    Yin,
+    Pyin,
+}
+
+fn new_detector(
+    algorithm: PitchAlgorithm,
+    size: usize,
+    padding: usize,
+) -> Box<dyn PitchDetector<f32>> {
+    match algorithm {
+        PitchAlgorithm::Autocorrelation => {
+            Box::new(AutocorrelationDetectorInternal::<f32>::new(size, padding))
+        }
+        PitchAlgorithm::McLeod => Box::new(McLeodDetectorInternal::<f32>::new(size, padding)),
+        PitchAlgorithm::Yin => Box::new(YinDetectorInternal::new(size, padding)),
+        PitchAlgorithm::Pyin => Box::new(PyinDetectorInternal::new(size, padding)),
+    }
+}
+
 #[wasm_bindgen]
 pub struct AutocorrelationDetector {
     wrapped: AutocorrelationDetectorInternal<f32>,
+    tuning: Tuning,
 }
 
 #[wasm_bindgen]
 impl AutocorrelationDetector {
     pub fn new(size: usize, padding: usize) -> Self {
         let wrapped = AutocorrelationDetectorInternal::<f32>::new(size, padding);
-        AutocorrelationDetector { wrapped }
+        AutocorrelationDetector {
+            wrapped,
+            tuning: Tuning::default(),
+        }
+    }
+
+    /// Set the tuning `detect` names notes in. Until it is set, A4 is 440 Hz in equal
+    /// temperament and notes are spelled with sharps.
+    pub fn set_tuning(&mut self, tuning: &Tuning) {
+        self.tuning = tuning.clone();
     }
 
     pub fn get_pitch(
@@ -41,18 +104,41 @@ impl AutocorrelationDetector {
                 .get_pitch(signal, sample_rate, power_threshold, clarity_threshold);
         pitch_option_to_output(result, pitch);
     }
+
+    pub fn detect(
+        &mut self,
+        signal: &[f32],
+        sample_rate: usize,
+        power_threshold: f32,
+        clarity_threshold: f32,
+    ) -> PitchResult {
+        let result =
+            self.wrapped
+                .get_pitch(signal, sample_rate, power_threshold, clarity_threshold);
+        PitchResult::new(result, signal, &self.tuning)
+    }
 }
 
 #[wasm_bindgen]
 pub struct McLeodDetector {
     wrapped: McLeodDetectorInternal<f32>,
+    tuning: Tuning,
 }
 
 #[wasm_bindgen]
 impl McLeodDetector {
     pub fn new(size: usize, padding: usize) -> Self {
         let wrapped = McLeodDetectorInternal::<f32>::new(size, padding);
-        McLeodDetector { wrapped }
+        McLeodDetector {
+            wrapped,
+            tuning: Tuning::default(),
+        }
+    }
+
+    /// Set the tuning `detect` names notes in. Until it is set, A4 is 440 Hz in equal
+    /// temperament and notes are spelled with sharps.
+    pub fn set_tuning(&mut self, tuning: &Tuning) {
+        self.tuning = tuning.clone();
     }
 
     pub fn get_pitch(
@@ -68,17 +154,136 @@ impl McLeodDetector {
                 .get_pitch(signal, sample_rate, power_threshold, clarity_threshold);
         pitch_option_to_output(result, pitch);
     }
+
+    pub fn detect(
+        &mut self,
+        signal: &[f32],
+        sample_rate: usize,
+        power_threshold: f32,
+        clarity_threshold: f32,
+    ) -> PitchResult {
+        let result =
+            self.wrapped
+                .get_pitch(signal, sample_rate, power_threshold, clarity_threshold);
+        PitchResult::new(result, signal, &self.tuning)
+    }
 }
 
-fn pitch_option_to_output(option: Option<Pitch<f32>>, output: &mut [f32]) {
-    match option {
-        Some(pitch) => {
-            output[0] = pitch.frequency;
-            output[1] = pitch.clarity;
+#[wasm_bindgen]
+pub struct YinDetector {
+    wrapped: YinDetectorInternal,
+    tuning: Tuning,
+}
+
+#[wasm_bindgen]
+impl YinDetector {
+    pub fn new(size: usize, padding: usize) -> Self {
+        let wrapped = YinDetectorInternal::new(size, padding);
+        YinDetector {
+            wrapped,
+            tuning: Tuning::default(),
         }
-        None => {
-            output[0] = -1.0;
-            output[1] = 0.0;
+    }
+
+    /// Set the tuning `detect` names notes in. Until it is set, A4 is 440 Hz in equal
+    /// temperament and notes are spelled with sharps.
+    pub fn set_tuning(&mut self, tuning: &Tuning) {
+        self.tuning = tuning.clone();
+    }
+
+    pub fn get_pitch(
+        &mut self,
+        signal: &[f32],
+        sample_rate: usize,
+        power_threshold: f32,
+        clarity_threshold: f32,
+        pitch: &mut [f32],
+    ) {
+        let result =
+            self.wrapped
+                .get_pitch(signal, sample_rate, power_threshold, clarity_threshold);
+        pitch_option_to_output(result, pitch);
+    }
+
+    pub fn detect(
+        &mut self,
+        signal: &[f32],
+        sample_rate: usize,
+        power_threshold: f32,
+        clarity_threshold: f32,
+    ) -> PitchResult {
+        let result =
+            self.wrapped
+                .get_pitch(signal, sample_rate, power_threshold, clarity_threshold);
+        PitchResult::new(result, signal, &self.tuning)
+    }
+}
+
+/// Probabilistic YIN. Unlike the other detectors, it remembers the windows it has seen, so
+/// consecutive calls to `get_pitch` should be consecutive windows of the same audio.
+#[wasm_bindgen]
+pub struct PyinDetector {
+    wrapped: PyinDetectorInternal,
+    tuning: Tuning,
+}
+
+#[wasm_bindgen]
+impl PyinDetector {
+    pub fn new(size: usize, padding: usize) -> Self {
+        let wrapped = PyinDetectorInternal::new(size, padding);
+        PyinDetector {
+            wrapped,
+            tuning: Tuning::default(),
         }
     }
+
+    /// Set the tuning `detect` names notes in. Until it is set, A4 is 440 Hz in equal
+    /// temperament and notes are spelled with sharps.
+    pub fn set_tuning(&mut self, tuning: &Tuning) {
+        self.tuning = tuning.clone();
+    }
+
+    pub fn get_pitch(
+        &mut self,
+        signal: &[f32],
+        sample_rate: usize,
+        power_threshold: f32,
+        clarity_threshold: f32,
+        pitch: &mut [f32],
+    ) {
+        let result =
+            self.wrapped
+                .get_pitch(signal, sample_rate, power_threshold, clarity_threshold);
+        pitch_option_to_output(result, pitch);
+    }
+
+    pub fn detect(
+        &mut self,
+        signal: &[f32],
+        sample_rate: usize,
+        power_threshold: f32,
+        clarity_threshold: f32,
+    ) -> PitchResult {
+        let result =
+            self.wrapped
+                .get_pitch(signal, sample_rate, power_threshold, clarity_threshold);
+        PitchResult::new(result, signal, &self.tuning)
+    }
+
+    /// Forget the windows seen so far.
+    pub fn reset(&mut self) {
+        self.wrapped.reset();
+    }
+}
+
+/// Write `[frequency, clarity]` into `output`, with a frequency of `-1.0` if there is no pitch.
+/// This is what `get_pitch` has always returned; `detect` returns a `PitchResult` instead.
+fn pitch_option_to_output(option: Option<Pitch<f32>>, output: &mut [f32]) {
+    let values = match option {
+        Some(pitch) => [pitch.frequency, pitch.clarity],
+        None => [-1.0, 0.0],
+    };
+    for (output, value) in output.iter_mut().zip(values.iter()) {
+        *output = *value;
+    }
 }
@@ -0,0 +1,303 @@
+//! Multi-pitch estimation, for when more than one person sings into the same microphone. The
+//! monophonic detectors lock onto one voice, or none. This estimator works on the spectrum
+//! instead: it finds the fundamental whose harmonics best explain the spectrum, removes those
+//! harmonics, and repeats for the next voice (Klapuri, 2006).
+//!
+//! Voices an octave apart share every harmonic of the higher voice, and are reported as one.
+
+use wasm_bindgen::prelude::*;
+
+use crate::spectrum::Spectrum;
+use crate::yin::power;
+
+/// The range of fundamentals searched, in Hz.
+const MIN_FREQUENCY: f32 = 60.0;
+const MAX_FREQUENCY: f32 = 1500.0;
+/// Candidate fundamentals are spaced this many cents apart before refinement.
+const CANDIDATE_SPACING: f32 = 10.0;
+const MAX_HARMONICS: usize = 20;
+/// Harmonics above this frequency are ignored, as the voice has little energy there.
+const MAX_HARMONIC_FREQUENCY: f32 = 5000.0;
+/// How far a harmonic can be from a multiple of the fundamental, in cents.
+const HARMONIC_TOLERANCE: f32 = 25.0;
+/// Parameters of the harmonic weights from Klapuri's paper, in Hz. Lower harmonics count for
+/// more, which stops a fundamental's subharmonics from explaining its spectrum just as well.
+const WEIGHT_ALPHA: f32 = 27.0;
+const WEIGHT_BETA: f32 = 320.0;
+/// Number of harmonics used to refine the fundamental.
+const REFINEMENT_HARMONICS: usize = 5;
+/// Half the width of the main lobe of the Hann window, in bins of the unpadded window.
+const MAIN_LOBE: f32 = 2.0;
+
+/// The pitches found in a window, strongest first.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MultiPitch {
+    frequencies: Vec<f32>,
+    saliences: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl MultiPitch {
+    pub fn len(&self) -> usize {
+        self.frequencies.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.frequencies.is_empty()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn frequencies(&self) -> Vec<f32> {
+        self.frequencies.clone()
+    }
+
+    /// The fraction of the window's spectral energy explained by each pitch, from `0` to `1`.
+    #[wasm_bindgen(getter)]
+    pub fn saliences(&self) -> Vec<f32> {
+        self.saliences.clone()
+    }
+}
+
+#[wasm_bindgen]
+pub struct MultiPitchDetector {
+    spectrum: Spectrum,
+    sample_rate: usize,
+    /// Half the width of the main lobe of a harmonic's peak, in bins of the padded spectrum.
+    lobe: usize,
+    magnitudes: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl MultiPitchDetector {
+    /// Windows need to be longer than for the monophonic detectors to resolve the harmonics of
+    /// two voices, e.g. 4096 samples at 44.1 kHz. `padding` zero pads each window.
+    pub fn new(
+        size: usize,
+        padding: usize,
+        sample_rate: usize,
+    ) -> Result<MultiPitchDetector, JsError> {
+        MultiPitchDetector::try_new(size, padding, sample_rate)
+            .map_err(|message| JsError::new(&message))
+    }
+
+    /// Find up to `max_pitches` pitches in `signal`. Pitches are found strongest first, and the
+    /// search stops at the first one that explains less than `salience_threshold` of the
+    /// window's spectral energy.
+    pub fn get_pitches(
+        &mut self,
+        signal: &[f32],
+        power_threshold: f32,
+        salience_threshold: f32,
+        max_pitches: usize,
+    ) -> MultiPitch {
+        let mut result = MultiPitch::default();
+        if power(signal) < power_threshold {
+            return result;
+        }
+
+        self.spectrum.magnitudes(signal, &mut self.magnitudes);
+        let bin_width = self.spectrum.bin_width(self.sample_rate);
+        let mut energy: Vec<f32> = self.magnitudes.iter().map(|m| m * m).collect();
+        let total_energy: f32 = energy.iter().sum();
+        if total_energy <= 0.0 {
+            return result;
+        }
+
+        while result.len() < max_pitches {
+            let residual: Vec<f32> = energy.iter().map(|e| e.sqrt()).collect();
+            let frequency = match best_fundamental(&residual, bin_width) {
+                Some(frequency) => refine(&residual, bin_width, frequency),
+                None => break,
+            };
+
+            // Cancel the harmonics of the fundamental, measuring how much energy they had.
+            let mut explained = 0.0;
+            for harmonic in harmonics(frequency, residual.len(), bin_width) {
+                let peak = match peak_bin(&residual, bin_width, harmonic) {
+                    Some(peak) => peak,
+                    None => continue,
+                };
+                let range =
+                    peak.saturating_sub(self.lobe)..(peak + self.lobe + 1).min(energy.len());
+                for bin in range {
+                    explained += energy[bin];
+                    energy[bin] = 0.0;
+                }
+            }
+
+            let salience = explained / total_energy;
+            if salience < salience_threshold {
+                break;
+            }
+            result.frequencies.push(frequency);
+            result.saliences.push(salience);
+        }
+        result
+    }
+}
+
+impl MultiPitchDetector {
+    /// As `new`, but with the error as a message.
+    pub fn try_new(size: usize, padding: usize, sample_rate: usize) -> Result<Self, String> {
+        if size == 0 {
+            return Err("The window size has to be at least 1 sample.".to_string());
+        }
+        if sample_rate == 0 {
+            return Err("The sample rate has to be above 0 Hz.".to_string());
+        }
+        Ok(MultiPitchDetector {
+            spectrum: Spectrum::new(size, padding),
+            sample_rate,
+            lobe: (MAIN_LOBE * (size + padding) as f32 / size as f32).ceil() as usize,
+            magnitudes: vec![],
+        })
+    }
+}
+
+/// The frequencies of the harmonics of `frequency` that are within the spectrum.
+fn harmonics(frequency: f32, bins: usize, bin_width: f32) -> impl Iterator<Item = f32> {
+    let max = MAX_HARMONIC_FREQUENCY.min((bins - 1) as f32 * bin_width);
+    (1..=MAX_HARMONICS)
+        .map(move |h| h as f32 * frequency)
+        .take_while(move |harmonic| *harmonic <= max)
+}
+
+/// The bin of the largest magnitude within the harmonic tolerance of `frequency`.
+fn peak_bin(magnitudes: &[f32], bin_width: f32, frequency: f32) -> Option<usize> {
+    let tolerance = 2f32.powf(HARMONIC_TOLERANCE / 1200.0);
+    let low = ((frequency / tolerance / bin_width).floor() as usize).max(1);
+    let high = ((frequency * tolerance / bin_width).ceil() as usize).min(magnitudes.len() - 1);
+    (low..=high)
+        .filter(|&bin| magnitudes[bin] > 0.0)
+        .max_by(|&a, &b| magnitudes[a].total_cmp(&magnitudes[b]))
+}
+
+/// The weighted sum of the magnitudes of the harmonics of `frequency`.
+fn salience(magnitudes: &[f32], bin_width: f32, frequency: f32) -> f32 {
+    harmonics(frequency, magnitudes.len(), bin_width)
+        .enumerate()
+        .filter_map(|(i, harmonic)| {
+            let peak = peak_bin(magnitudes, bin_width, harmonic)?;
+            let weight = (frequency + WEIGHT_ALPHA) / ((i + 1) as f32 * frequency + WEIGHT_BETA);
+            Some(weight * magnitudes[peak])
+        })
+        .sum()
+}
+
+/// The candidate fundamental with the largest salience.
+fn best_fundamental(magnitudes: &[f32], bin_width: f32) -> Option<f32> {
+    let candidates = (1200.0 * (MAX_FREQUENCY / MIN_FREQUENCY).log2() / CANDIDATE_SPACING) as usize;
+    (0..=candidates)
+        .map(|i| MIN_FREQUENCY * 2f32.powf(i as f32 * CANDIDATE_SPACING / 1200.0))
+        .map(|frequency| (frequency, salience(magnitudes, bin_width, frequency)))
+        .filter(|(_, salience)| *salience > 0.0)
+        .max_by(|(_, a), (_, b)| a.total_cmp(b))
+        .map(|(frequency, _)| frequency)
+}
+
+/// Refine a candidate fundamental from the interpolated positions of its lowest harmonics,
+/// weighted by their magnitudes.
+fn refine(magnitudes: &[f32], bin_width: f32, frequency: f32) -> f32 {
+    let (mut sum, mut weights) = (0.0, 0.0);
+    for (i, harmonic) in harmonics(frequency, magnitudes.len(), bin_width)
+        .take(REFINEMENT_HARMONICS)
+        .enumerate()
+    {
+        let peak = match peak_bin(magnitudes, bin_width, harmonic) {
+            Some(peak) if peak > 0 && peak + 1 < magnitudes.len() => peak,
+            _ => continue,
+        };
+        let (before, at, after) = (magnitudes[peak - 1], magnitudes[peak], magnitudes[peak + 1]);
+        let curvature = before - 2.0 * at + after;
+        let offset = if curvature < 0.0 {
+            (before - after) / (2.0 * curvature)
+        } else {
+            0.0
+        };
+        sum += at * (peak as f32 + offset) * bin_width / (i + 1) as f32;
+        weights += at;
+    }
+    if weights > 0.0 {
+        sum / weights
+    } else {
+        frequency
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: usize = 44_100;
+
+    /// A vowel-like tone with six harmonics.
+    fn voice(frequency: f32, amplitude: f32) -> Vec<f32> {
+        let harmonics = [1.0, 0.8, 0.6, 0.4, 0.3, 0.2];
+        (0..4096)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                harmonics
+                    .iter()
+                    .enumerate()
+                    .map(|(h, weight)| {
+                        let phase = std::f32::consts::TAU * frequency * (h + 1) as f32 * t;
+                        amplitude * weight * (phase + h as f32).sin()
+                    })
+                    .sum::<f32>()
+            })
+            .collect()
+    }
+
+    fn cents(actual: f32, expected: f32) -> f32 {
+        1200.0 * (actual / expected).log2()
+    }
+
+    #[test]
+    fn both_voices_of_a_duet_are_found() {
+        // A fifth apart, so that every third harmonic of the lower voice is shared.
+        let duet: Vec<f32> = voice(220.0, 0.2)
+            .iter()
+            .zip(voice(330.0, 0.15))
+            .map(|(a, b)| a + b)
+            .collect();
+        let pitches = MultiPitchDetector::try_new(4096, 4096, SAMPLE_RATE)
+            .unwrap()
+            .get_pitches(&duet, 0.01, 0.05, 3);
+
+        assert_eq!(pitches.len(), 2, "{:?}", pitches);
+        let mut frequencies = pitches.frequencies();
+        frequencies.sort_by(f32::total_cmp);
+        assert!(cents(frequencies[0], 220.0).abs() < 10.0, "{:?}", pitches);
+        assert!(cents(frequencies[1], 330.0).abs() < 10.0, "{:?}", pitches);
+        assert!(
+            pitches.saliences().iter().sum::<f32>() > 0.8,
+            "{:?}",
+            pitches
+        );
+    }
+
+    #[test]
+    fn a_single_voice_is_one_pitch() {
+        let mut detector = MultiPitchDetector::try_new(4096, 4096, SAMPLE_RATE).unwrap();
+        let pitches = detector.get_pitches(&voice(150.0, 0.2), 0.01, 0.05, 3);
+        assert_eq!(pitches.len(), 1, "{:?}", pitches);
+        assert!(
+            cents(pitches.frequencies()[0], 150.0).abs() < 10.0,
+            "{:?}",
+            pitches
+        );
+        assert!(pitches.saliences()[0] > 0.9, "{:?}", pitches);
+
+        assert!(detector.get_pitches(&[0.0; 4096], 0.01, 0.05, 3).is_empty());
+    }
+
+    #[test]
+    fn empty_windows_and_sample_rates_are_rejected() {
+        assert!(MultiPitchDetector::try_new(0, 4096, SAMPLE_RATE).is_err());
+        assert!(MultiPitchDetector::try_new(4096, 4096, 0).is_err());
+        // Even a window too short to resolve any pitch finds nothing rather than failing.
+        let mut detector = MultiPitchDetector::try_new(1, 0, SAMPLE_RATE).unwrap();
+        assert!(detector.get_pitches(&[0.5], 0.01, 0.05, 3).is_empty());
+    }
+}
@@ -0,0 +1,345 @@
+//! Conversion between frequencies and notes.
+//!
+//! The free functions assume equal temperament with A4 at 440 Hz. A `Tuning` converts with
+//! another reference pitch or temperament, and spells notes to suit a key.
+
+use wasm_bindgen::prelude::*;
+
+const NOTE_NAMES: [&str; 12] = [
+    "C", "C#", "D", "D#", "E", "F", "F#", "G", "G#", "A", "A#", "B",
+];
+/// The note letters in the order they appear on the line of fifths, from F.
+const FIFTHS_LETTERS: [char; 7] = ['F', 'C', 'G', 'D', 'A', 'E', 'B'];
+/// The pitch class of each note letter, in the order of `FIFTHS_LETTERS`.
+const FIFTHS_PITCH_CLASSES: [i32; 7] = [5, 0, 7, 2, 9, 4, 11];
+
+/// The range of reference pitches for A4, in Hz, from Baroque pitch to the highest in use.
+const MIN_REFERENCE: f32 = 415.0;
+const MAX_REFERENCE: f32 = 466.0;
+
+/// Five-limit just intonation, as ratios to the tonic of each semitone above it.
+const JUST_RATIOS: [f32; 12] = [
+    1.0,
+    16.0 / 15.0,
+    9.0 / 8.0,
+    6.0 / 5.0,
+    5.0 / 4.0,
+    4.0 / 3.0,
+    45.0 / 32.0,
+    3.0 / 2.0,
+    8.0 / 5.0,
+    5.0 / 3.0,
+    9.0 / 5.0,
+    15.0 / 8.0,
+];
+/// How much wider a pure fifth is than an equal-tempered one, in cents.
+const PYTHAGOREAN_COMMA_PER_FIFTH: f32 = 1.955;
+
+/// The fractional MIDI note number of `frequency`, with A4 at 440 Hz.
+pub fn frequency_to_midi(frequency: f32) -> f32 {
+    69.0 + 12.0 * (frequency / 440.0).log2()
+}
+
+/// The name of a MIDI note with its octave, e.g. `"A4"` for 69.
+pub fn note_name(midi: u8) -> String {
+    let octave = midi as i32 / 12 - 1;
+    format!("{}{}", NOTE_NAMES[midi as usize % 12], octave)
+}
+
+/// Temperaments a `Tuning` can use. Just intonation and Pythagorean tuning are built on the
+/// tonic of the tuning's key, or on C if it has no key.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Temperament {
+    Equal,
+    Just,
+    Pythagorean,
+}
+
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Mode {
+    Major,
+    Minor,
+}
+
+/// The note closest to a frequency under a `Tuning`.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct TunedNote {
+    pub midi: u8,
+    /// The name of the note with its octave, spelled for the tuning's key, e.g. `"Gb4"`.
+    pub name: String,
+    /// How far the frequency is from the note, in cents.
+    pub cents: f32,
+    /// The frequency of the note itself, in Hz.
+    pub frequency: f32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Key {
+    tonic: u8,
+    mode: Mode,
+}
+
+/// A reference pitch, temperament and key to convert between frequencies and notes with.
+#[wasm_bindgen]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Tuning {
+    reference: f32,
+    /// How far each semitone above the tonic is from equal temperament, in cents.
+    offsets: [f32; 12],
+    key: Option<Key>,
+}
+
+impl Default for Tuning {
+    fn default() -> Self {
+        Tuning {
+            reference: 440.0,
+            offsets: [0.0; 12],
+            key: None,
+        }
+    }
+}
+
+#[wasm_bindgen]
+impl Tuning {
+    /// Equal temperament with A4 at `reference` Hz, which has to be from 415 to 466 Hz. With no
+    /// key, notes are spelled with sharps.
+    pub fn new(reference: f32) -> Result<Tuning, JsError> {
+        Tuning::with_reference(reference).map_err(|message| JsError::new(&message))
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn reference(&self) -> f32 {
+        self.reference
+    }
+
+    /// Spell notes for the key with the pitch class `tonic` (`0` for C to `11` for B), and
+    /// build just and Pythagorean temperaments on it.
+    pub fn set_key(&mut self, tonic: u8, mode: Mode) {
+        self.key = Some(Key {
+            tonic: tonic % 12,
+            mode,
+        });
+    }
+
+    pub fn clear_key(&mut self) {
+        self.key = None;
+    }
+
+    pub fn set_temperament(&mut self, temperament: Temperament) {
+        for (semitones, offset) in self.offsets.iter_mut().enumerate() {
+            *offset = match temperament {
+                Temperament::Equal => 0.0,
+                Temperament::Just => {
+                    1200.0 * JUST_RATIOS[semitones].log2() - 100.0 * semitones as f32
+                }
+                Temperament::Pythagorean => {
+                    PYTHAGOREAN_COMMA_PER_FIFTH * fifths_from_c(semitones as i32) as f32
+                }
+            };
+        }
+    }
+
+    /// Use a custom temperament, given as the size in cents of each of the twelve semitones
+    /// above the tonic, starting with the tonic itself at `0`. Missing semitones are equal
+    /// tempered, and extra ones are ignored.
+    pub fn set_custom_temperament(&mut self, cents: &[f32]) {
+        for (semitones, offset) in self.offsets.iter_mut().enumerate() {
+            *offset = cents
+                .get(semitones)
+                .map_or(0.0, |cents| cents - 100.0 * semitones as f32);
+        }
+    }
+
+    /// The fractional MIDI note number of `frequency` in equal temperament from the reference
+    /// pitch.
+    pub fn frequency_to_midi(&self, frequency: f32) -> f32 {
+        69.0 + 12.0 * (frequency / self.reference).log2()
+    }
+
+    /// The frequency of a note in this tuning. The tonic is kept at its equal-tempered pitch,
+    /// and the other notes are tuned relative to it.
+    pub fn midi_to_frequency(&self, midi: u8) -> f32 {
+        self.reference * 2f32.powf((self.tuned_midi(midi as i32) - 69.0) / 12.0)
+    }
+
+    /// The note in this tuning closest to `frequency`.
+    pub fn nearest_note(&self, frequency: f32) -> TunedNote {
+        let midi = self.frequency_to_midi(frequency);
+        let rounded = midi.round() as i32;
+        let nearest = (rounded - 1..=rounded + 1)
+            .map(|note| note.clamp(0, 127))
+            .min_by(|&a, &b| {
+                let distance = |note| (midi - self.tuned_midi(note)).abs();
+                distance(a).total_cmp(&distance(b))
+            })
+            .unwrap_or(rounded) as u8;
+
+        TunedNote {
+            midi: nearest,
+            name: self.note_name(nearest),
+            cents: 100.0 * (midi - self.tuned_midi(nearest as i32)),
+            frequency: self.midi_to_frequency(nearest),
+        }
+    }
+
+    /// The name of a MIDI note with its octave, spelled for the key, e.g. `"F#4"` in D major
+    /// and `"Gb4"` in Db major. Notes outside the key get their usual spelling in it, e.g.
+    /// Bb in D major and F# in C major.
+    pub fn note_name(&self, midi: u8) -> String {
+        let key = match self.key {
+            Some(key) => key,
+            None => return note_name(midi),
+        };
+        let major_tonic = match key.mode {
+            Mode::Major => key.tonic,
+            Mode::Minor => (key.tonic + 3) % 12,
+        };
+        let key_fifths = fifths_from_c(major_tonic as i32);
+
+        // The notes of a major key are at `key_fifths - 1` to `key_fifths + 5` on the line of
+        // fifths. Each note is spelled at the position closest to them, which puts the flat
+        // sixth and sharp fourth either side. Minor keys lean one fifth sharper, for their
+        // raised sixth and seventh. Spellings with double accidentals are never used.
+        let centre = 2 * key_fifths
+            + match key.mode {
+                Mode::Major => 3,
+                Mode::Minor => 5,
+            };
+        let base = fifths_from_c(midi as i32);
+        let position = [base - 12, base, base + 12]
+            .iter()
+            .copied()
+            .filter(|&position| (-8..=12).contains(&position))
+            .min_by_key(|&position| (2 * position - centre).abs())
+            .unwrap_or(base);
+
+        let letter = (position + 1).rem_euclid(7) as usize;
+        let accidentals = (position + 1).div_euclid(7);
+        let accidental = match accidentals {
+            1 => "#",
+            -1 => "b",
+            _ => "",
+        };
+        // The octave goes with the letter, so B#3 and Cb4 are written in the octave of B and C.
+        let natural = midi as i32 - accidentals;
+        let octave = (natural - FIFTHS_PITCH_CLASSES[letter]).div_euclid(12) - 1;
+        format!("{}{}{}", FIFTHS_LETTERS[letter], accidental, octave)
+    }
+}
+
+impl Tuning {
+    /// Equal temperament with A4 at `reference` Hz, as `new` but with the error as a message.
+    pub fn with_reference(reference: f32) -> Result<Self, String> {
+        if !(MIN_REFERENCE..=MAX_REFERENCE).contains(&reference) {
+            return Err(format!(
+                "The reference pitch has to be from {} to {} Hz, not {} Hz.",
+                MIN_REFERENCE, MAX_REFERENCE, reference
+            ));
+        }
+        Ok(Tuning {
+            reference,
+            ..Tuning::default()
+        })
+    }
+
+    /// The MIDI note number, in equal temperament from the reference pitch, that `midi` is
+    /// tuned to.
+    fn tuned_midi(&self, midi: i32) -> f32 {
+        let tonic = self.key.map_or(0, |key| key.tonic as i32);
+        midi as f32 + self.offsets[(midi - tonic).rem_euclid(12) as usize] / 100.0
+    }
+}
+
+/// The position of a pitch class on the line of fifths, from `-5` (Db) to `6` (F#) with C at
+/// `0`.
+fn fifths_from_c(pitch_class: i32) -> i32 {
+    let fifths = (pitch_class * 7).rem_euclid(12);
+    if fifths > 6 {
+        fifths - 12
+    } else {
+        fifths
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn frequencies_are_converted_to_notes() {
+        assert_eq!(frequency_to_midi(440.0), 69.0);
+        assert!((frequency_to_midi(261.63) - 60.0).abs() < 0.01);
+        assert_eq!(note_name(69), "A4");
+        assert_eq!(note_name(61), "C#4");
+        assert_eq!(note_name(0), "C-1");
+    }
+
+    #[test]
+    fn reference_pitch_and_temperament_change_the_notes() {
+        let tuning = Tuning::with_reference(415.0).unwrap();
+        assert_eq!(tuning.midi_to_frequency(69), 415.0);
+        // A4 at 440 Hz is a Bb at Baroque pitch.
+        let note = tuning.nearest_note(440.0);
+        assert_eq!((note.midi, note.name.as_str()), (70, "A#4"));
+        assert!((note.cents - 1.3).abs() < 0.1, "{:?}", note);
+        assert!(Tuning::with_reference(300.0).is_err());
+        assert!(Tuning::with_reference(f32::NAN).is_err());
+
+        // In just intonation on C, the major third is a pure 5:4 and the fifth a pure 3:2.
+        let mut tuning = Tuning::default();
+        tuning.set_key(0, Mode::Major);
+        tuning.set_temperament(Temperament::Just);
+        let c4 = tuning.midi_to_frequency(60);
+        assert!((c4 - 261.63).abs() < 0.01);
+        assert!((tuning.midi_to_frequency(64) / c4 - 1.25).abs() < 1e-4);
+        assert!((tuning.midi_to_frequency(67) / c4 - 1.5).abs() < 1e-4);
+        // An equal-tempered E is 13.7 cents sharp of a just one.
+        let note = tuning.nearest_note(329.63);
+        assert_eq!(note.midi, 64);
+        assert!((note.cents - 13.7).abs() < 0.1, "{:?}", note);
+
+        tuning.set_temperament(Temperament::Pythagorean);
+        assert!((tuning.midi_to_frequency(67) / c4 - 1.5).abs() < 1e-4);
+        assert!((tuning.midi_to_frequency(62) / c4 - 9.0 / 8.0).abs() < 1e-4);
+
+        // Quarter-comma meantone, with the major third pure.
+        let meantone = [
+            0.0, 76.0, 193.2, 310.3, 386.3, 503.4, 579.5, 696.6, 772.6, 889.7, 1006.8, 1082.9,
+        ];
+        tuning.set_custom_temperament(&meantone);
+        assert!((tuning.midi_to_frequency(64) / c4 - 1.25).abs() < 1e-3);
+        tuning.set_custom_temperament(&[]);
+        assert!((tuning.midi_to_frequency(64) - 329.63).abs() < 0.01);
+    }
+
+    #[test]
+    fn notes_are_spelled_for_the_key() {
+        let mut tuning = Tuning::default();
+        let names = |tuning: &Tuning, notes: &[u8]| -> Vec<String> {
+            notes.iter().map(|&midi| tuning.note_name(midi)).collect()
+        };
+
+        tuning.set_key(2, Mode::Major);
+        assert_eq!(names(&tuning, &[66, 61, 70]), ["F#4", "C#4", "Bb4"]);
+        tuning.set_key(1, Mode::Major);
+        assert_eq!(names(&tuning, &[66, 61, 70]), ["Gb4", "Db4", "Bb4"]);
+        // C minor has three flats, and spells its leading tone as B.
+        tuning.set_key(0, Mode::Minor);
+        assert_eq!(
+            names(&tuning, &[63, 68, 71, 66]),
+            ["Eb4", "Ab4", "B4", "Gb4"]
+        );
+        // F# major has an E#, written in the octave of E.
+        tuning.set_key(6, Mode::Major);
+        assert_eq!(names(&tuning, &[65, 67, 71]), ["E#4", "G4", "B4"]);
+        // Gb minor is spelled as F# minor.
+        tuning.set_key(6, Mode::Minor);
+        assert_eq!(names(&tuning, &[69, 70]), ["A4", "A#4"]);
+
+        tuning.clear_key();
+        assert_eq!(names(&tuning, &[66, 70]), ["F#4", "A#4"]);
+    }
+}
@@ -0,0 +1,347 @@
+//! Note segmentation: turning a pitch contour into discrete notes, for scoring and for showing
+//! what was sung. A sung note is rarely a steady pitch, so the segmenter has to tell vibrato,
+//! glides between notes and short unvoiced gaps (consonants, breaths) apart from new notes.
+
+use std::collections::VecDeque;
+
+use wasm_bindgen::prelude::*;
+
+use crate::music::frequency_to_midi;
+use crate::tracker::{PitchContour, PitchFrame};
+
+const DEFAULT_MIN_DURATION: f64 = 0.08;
+const DEFAULT_MAX_GAP: f64 = 0.1;
+const DEFAULT_PITCH_TOLERANCE: f32 = 0.7;
+const DEFAULT_MIN_CHANGE_DURATION: f64 = 0.1;
+
+/// A sung note.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Note {
+    /// Start of the note, in seconds.
+    pub onset: f64,
+    /// End of the note, in seconds.
+    pub offset: f64,
+    /// The MIDI note number closest to the note's pitch.
+    pub midi: u8,
+    /// How far the note's pitch is from `midi`, from `-50` to `50` cents.
+    pub cents: f32,
+}
+
+/// The note currently being sung.
+struct ActiveNote {
+    onset: f64,
+    /// Time of the last frame that matched the note's pitch.
+    last_frame: f64,
+    /// Pitches of the frames that matched the note, as fractional MIDI note numbers.
+    pitches: Vec<f32>,
+}
+
+#[wasm_bindgen]
+pub struct NoteSegmenter {
+    min_duration: f64,
+    max_gap: f64,
+    pitch_tolerance: f32,
+    min_change_duration: f64,
+    note: Option<ActiveNote>,
+    /// The most recent voiced frames that don't match the current note, as times and
+    /// fractional MIDI note numbers. Once they have settled for long enough, they become a new
+    /// note.
+    pending: VecDeque<(f64, f32)>,
+    /// Time of the first unvoiced frame since the current note was last voiced.
+    gap_start: Option<f64>,
+    last_time: Option<f64>,
+    /// The time between frames, estimated from the last two frames.
+    frame_step: f64,
+}
+
+impl Default for NoteSegmenter {
+    fn default() -> Self {
+        NoteSegmenter::new()
+    }
+}
+
+#[wasm_bindgen]
+impl NoteSegmenter {
+    pub fn new() -> Self {
+        NoteSegmenter {
+            min_duration: DEFAULT_MIN_DURATION,
+            max_gap: DEFAULT_MAX_GAP,
+            pitch_tolerance: DEFAULT_PITCH_TOLERANCE,
+            min_change_duration: DEFAULT_MIN_CHANGE_DURATION,
+            note: None,
+            pending: VecDeque::new(),
+            gap_start: None,
+            last_time: None,
+            frame_step: 0.0,
+        }
+    }
+
+    /// Notes shorter than this many seconds are dropped.
+    pub fn set_min_duration(&mut self, seconds: f64) {
+        self.min_duration = seconds;
+    }
+
+    /// Unvoiced gaps up to this many seconds long don't end a note.
+    pub fn set_max_gap(&mut self, seconds: f64) {
+        self.max_gap = seconds;
+    }
+
+    /// How far, in semitones, the pitch can stray from the middle of a note, e.g. with vibrato,
+    /// before it may be a different note.
+    pub fn set_pitch_tolerance(&mut self, semitones: f32) {
+        self.pitch_tolerance = semitones;
+    }
+
+    /// How long, in seconds, the pitch has to settle somewhere else before it is a new note.
+    /// Shorter excursions are treated as part of the current note, and pitches that never
+    /// settle, like glides, aren't part of any note.
+    pub fn set_min_change_duration(&mut self, seconds: f64) {
+        self.min_change_duration = seconds;
+    }
+
+    /// Add the pitch of the frame at `time`, in seconds, returning the note it ends, if any.
+    /// A `frequency` of zero or less means the frame is unvoiced.
+    pub fn push(&mut self, time: f64, frequency: f32) -> Option<Note> {
+        self.push_frame(PitchFrame {
+            time,
+            frequency: Some(frequency).filter(|frequency| *frequency > 0.0),
+            clarity: 0.0,
+        })
+    }
+
+    /// Add every frame of `contour`, returning the notes they end.
+    pub fn process(&mut self, contour: &PitchContour) -> Vec<Note> {
+        contour
+            .times()
+            .into_iter()
+            .zip(contour.frequencies())
+            .filter_map(|(time, frequency)| self.push(time, frequency))
+            .collect()
+    }
+
+    /// End the note being sung, if any, e.g. at the end of a recording.
+    pub fn finish(&mut self) -> Option<Note> {
+        self.pending.clear();
+        self.gap_start = None;
+        self.last_time = None;
+        let note = self.note.take()?;
+        self.to_note(note)
+    }
+}
+
+impl NoteSegmenter {
+    /// Add the next pitch frame, returning the note it ends, if any.
+    pub fn push_frame(&mut self, frame: PitchFrame) -> Option<Note> {
+        if let Some(last_time) = self.last_time {
+            if frame.time > last_time {
+                self.frame_step = frame.time - last_time;
+            }
+        }
+        self.last_time = Some(frame.time);
+
+        let pitch = match frame.frequency {
+            Some(frequency) => frequency_to_midi(frequency),
+            None => return self.push_unvoiced(frame.time),
+        };
+        self.gap_start = None;
+
+        if let Some(note) = &mut self.note {
+            if (pitch - median(&note.pitches)).abs() <= self.pitch_tolerance {
+                // The pitch came back before settling elsewhere, so the frames in between were
+                // part of the note too, e.g. the far side of a wide vibrato.
+                note.pitches
+                    .extend(self.pending.drain(..).map(|(_, pitch)| pitch));
+                note.pitches.push(pitch);
+                note.last_frame = frame.time;
+                return None;
+            }
+        }
+
+        // Drop the start of the pending frames until they are all within the tolerance of
+        // their median, so that a glide into the new note isn't counted as part of it.
+        self.pending.push_back((frame.time, pitch));
+        while let Some(&(_, first)) = self.pending.front() {
+            let pitches: Vec<f32> = self.pending.iter().map(|(_, pitch)| *pitch).collect();
+            if (first - median(&pitches)).abs() <= self.pitch_tolerance {
+                break;
+            }
+            self.pending.pop_front();
+        }
+
+        let settled_since = self.pending.front().map_or(frame.time, |(time, _)| *time);
+        if frame.time - settled_since + self.frame_step < self.min_change_duration {
+            return None;
+        }
+        let new_note = ActiveNote {
+            onset: settled_since,
+            last_frame: frame.time,
+            pitches: self.pending.drain(..).map(|(_, pitch)| pitch).collect(),
+        };
+        let ended = self.note.replace(new_note)?;
+        self.to_note(ended)
+    }
+
+    fn push_unvoiced(&mut self, time: f64) -> Option<Note> {
+        self.pending.clear();
+        self.note.as_ref()?;
+        let gap_start = *self.gap_start.get_or_insert(time);
+        if time - gap_start < self.max_gap {
+            return None;
+        }
+
+        self.gap_start = None;
+        let note = self.note.take()?;
+        self.to_note(note)
+    }
+
+    /// Summarize a note that has ended, unless it is too short to count.
+    fn to_note(&self, note: ActiveNote) -> Option<Note> {
+        let offset = note.last_frame + self.frame_step;
+        if offset - note.onset < self.min_duration {
+            return None;
+        }
+        let pitch = median(&note.pitches).clamp(0.0, 127.0);
+        let midi = pitch.round();
+        Some(Note {
+            onset: note.onset,
+            offset,
+            midi: midi as u8,
+            cents: (pitch - midi) * 100.0,
+        })
+    }
+}
+
+fn median(values: &[f32]) -> f32 {
+    let mut sorted = values.to_vec();
+    sorted.sort_by(f32::total_cmp);
+    sorted[sorted.len() / 2]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Frames every 10 ms.
+    const STEP: f64 = 0.01;
+
+    fn midi_to_frequency(midi: f32) -> f32 {
+        440.0 * 2f32.powf((midi - 69.0) / 12.0)
+    }
+
+    /// Segment a contour given as fractional MIDI note numbers, `None` being unvoiced.
+    fn segment(pitches: &[Option<f32>]) -> Vec<Note> {
+        let mut segmenter = NoteSegmenter::new();
+        let mut notes: Vec<Note> = pitches
+            .iter()
+            .enumerate()
+            .filter_map(|(i, pitch)| {
+                segmenter.push_frame(PitchFrame {
+                    time: i as f64 * STEP,
+                    frequency: pitch.map(midi_to_frequency),
+                    clarity: 1.0,
+                })
+            })
+            .collect();
+        notes.extend(segmenter.finish());
+        notes
+    }
+
+    fn steady(midi: f32, seconds: f64) -> Vec<Option<f32>> {
+        vec![Some(midi); (seconds / STEP).round() as usize]
+    }
+
+    fn assert_close(actual: f64, expected: f64) {
+        assert!(
+            (actual - expected).abs() < 0.015,
+            "{} != {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn vibrato_stays_within_one_note() {
+        // C4 with a 5 Hz vibrato of ±80 cents, then D4 with the same vibrato, 20 cents flat.
+        let vibrato = |centre: f32, start: usize| {
+            (start..start + 100).map(move |i| {
+                let phase = std::f64::consts::TAU * 5.0 * i as f64 * STEP;
+                Some(centre + 0.8 * phase.sin() as f32)
+            })
+        };
+        let pitches: Vec<_> = vibrato(60.0, 0).chain(vibrato(61.8, 100)).collect();
+        let notes = segment(&pitches);
+
+        assert_eq!(notes.len(), 2, "{:?}", notes);
+        assert_eq!(notes[0].midi, 60);
+        assert!(notes[0].cents.abs() < 10.0);
+        assert_close(notes[0].onset, 0.0);
+        assert_eq!(notes[1].midi, 62);
+        assert!((notes[1].cents + 20.0).abs() < 10.0);
+        assert_close(notes[1].offset, 2.0);
+        // The change of note is found despite the vibrato crossing between the notes.
+        assert!((notes[0].offset - 1.0).abs() < 0.1, "{:?}", notes);
+        assert!((notes[1].onset - 1.0).abs() < 0.1, "{:?}", notes);
+    }
+
+    #[test]
+    fn glides_are_not_part_of_either_note() {
+        // A3, a 150 ms glide up to C4, then C4.
+        let glide = (1..15).map(|i| Some(57.0 + 3.0 * i as f32 / 15.0));
+        let pitches: Vec<_> = steady(57.0, 0.5)
+            .into_iter()
+            .chain(glide)
+            .chain(steady(60.0, 0.5))
+            .collect();
+        let notes = segment(&pitches);
+
+        assert_eq!(notes.len(), 2, "{:?}", notes);
+        assert_eq!((notes[0].midi, notes[1].midi), (57, 60));
+        // The notes end and start within a tolerance's worth of the glide.
+        assert!(
+            notes[0].offset > 0.5 && notes[0].offset < 0.56,
+            "{:?}",
+            notes
+        );
+        assert!(
+            notes[1].onset > 0.58 && notes[1].onset <= 0.64,
+            "{:?}",
+            notes
+        );
+    }
+
+    #[test]
+    fn short_gaps_are_bridged_and_long_gaps_end_notes() {
+        let pitches: Vec<_> = steady(64.0, 0.3)
+            .into_iter()
+            .chain(vec![None; 5])
+            .chain(steady(64.0, 0.3))
+            .chain(vec![None; 30])
+            .chain(steady(64.0, 0.3))
+            .collect();
+        let notes = segment(&pitches);
+
+        assert_eq!(notes.len(), 2, "{:?}", notes);
+        assert_close(notes[0].onset, 0.0);
+        assert_close(notes[0].offset, 0.65);
+        assert_close(notes[1].onset, 0.95);
+        assert_close(notes[1].offset, 1.25);
+    }
+
+    #[test]
+    fn blips_are_not_notes() {
+        let pitches: Vec<_> = steady(67.0, 0.05)
+            .into_iter()
+            .chain(vec![None; 20])
+            .chain(steady(67.0, 0.5))
+            .chain(steady(75.0, 0.05))
+            .chain(vec![None; 20])
+            .collect();
+        let notes = segment(&pitches);
+
+        assert_eq!(notes.len(), 1, "{:?}", notes);
+        assert_eq!(notes[0].midi, 67);
+        assert_close(notes[0].onset, 0.25);
+        assert_close(notes[0].offset, 0.75);
+    }
+}
@@ -0,0 +1,231 @@
+//! Onset detection for streamed audio, for checking that a singer comes in on time. Each window's
+//! spectrum is compared with the previous one, and an onset is reported wherever the spectral
+//! flux, the total rise in (log compressed) magnitude across the spectrum, peaks clearly above
+//! its recent level. A new note raises the energy at its harmonics, whether it follows silence
+//! or a different note.
+
+use std::collections::VecDeque;
+
+use wasm_bindgen::prelude::*;
+
+use crate::spectrum::Spectrum;
+use crate::windower::Windower;
+use crate::yin::power;
+
+const DEFAULT_THRESHOLD: f32 = 2.0;
+const DEFAULT_MIN_INTERVAL: f64 = 0.05;
+/// Compression applied to magnitudes before taking the flux, `ln(1 + COMPRESSION * magnitude)`,
+/// so that quiet notes count as well as loud ones.
+const COMPRESSION: f32 = 100.0;
+/// An onset has to be the highest flux from this long before it until `POST_MAX` after it, in
+/// seconds.
+const PRE_MAX: f64 = 0.03;
+/// How long after an onset the flux is looked at before it is reported, in seconds. This is the
+/// latency of the detector on top of the window.
+const POST_MAX: f64 = 0.01;
+/// The flux is compared with its average over this long before an onset, in seconds.
+const PRE_AVERAGE: f64 = 0.1;
+
+#[wasm_bindgen]
+pub struct OnsetDetector {
+    spectrum: Spectrum,
+    windower: Windower,
+    sample_rate: usize,
+    power_threshold: f32,
+    threshold: f32,
+    min_interval: f64,
+    magnitudes: Vec<f32>,
+    /// The compressed magnitudes of the previous window.
+    previous: Vec<f32>,
+    /// The flux of the most recent windows, enough to pick the peaks of the flux.
+    flux: VecDeque<f32>,
+    /// Position of the start of the window after the last one in `flux`, in samples.
+    flux_end: u64,
+    last_onset: Option<f64>,
+}
+
+#[wasm_bindgen]
+impl OnsetDetector {
+    /// Detect onsets in windows of `window_size` samples, starting a new window every
+    /// `hop_size` samples. Windows with less power than `power_threshold` are treated as
+    /// silence, so that background noise doesn't cause onsets.
+    pub fn new(
+        window_size: usize,
+        hop_size: usize,
+        sample_rate: usize,
+        power_threshold: f32,
+    ) -> Self {
+        OnsetDetector {
+            spectrum: Spectrum::new(window_size, 0),
+            windower: Windower::new(window_size, hop_size),
+            sample_rate,
+            power_threshold,
+            threshold: DEFAULT_THRESHOLD,
+            min_interval: DEFAULT_MIN_INTERVAL,
+            magnitudes: vec![],
+            previous: vec![],
+            flux: VecDeque::new(),
+            flux_end: 0,
+            last_onset: None,
+        }
+    }
+
+    /// Set how far the flux has to rise above its recent average to be an onset. Higher values
+    /// miss soft onsets, lower values find onsets in vibrato and noise.
+    pub fn set_threshold(&mut self, threshold: f32) {
+        self.threshold = threshold.max(0.0);
+    }
+
+    /// Set the shortest time between two onsets, in seconds.
+    pub fn set_min_interval(&mut self, seconds: f64) {
+        self.min_interval = seconds.max(0.0);
+    }
+
+    /// Add a chunk of audio of any length, returning the times of the onsets found, in seconds
+    /// since the detector started. An onset is reported a little after the window that
+    /// completes it, once the flux has been seen to fall again.
+    pub fn process(&mut self, chunk: &[f32]) -> Vec<f64> {
+        self.windower.push(chunk);
+
+        let mut onsets = vec![];
+        while let Some((start, window)) = self.windower.next_window() {
+            let silent = power(window) < self.power_threshold;
+            self.spectrum.magnitudes(window, &mut self.magnitudes);
+            let flux = self.flux();
+            self.flux.push_back(if silent { 0.0 } else { flux });
+            self.flux_end = start + self.windower.hop_size() as u64;
+            onsets.extend(self.pick_peak());
+        }
+        onsets
+    }
+
+    /// Forget all audio seen so far, and start the timestamps from zero again.
+    pub fn reset(&mut self) {
+        self.windower.reset();
+        self.previous.clear();
+        self.flux.clear();
+        self.flux_end = 0;
+        self.last_onset = None;
+    }
+}
+
+impl OnsetDetector {
+    /// The spectral flux from the previous window to the one in `magnitudes`, which becomes the
+    /// previous window.
+    fn flux(&mut self) -> f32 {
+        self.previous.resize(self.magnitudes.len(), 0.0);
+        let mut flux = 0.0;
+        for (magnitude, previous) in self.magnitudes.iter().zip(self.previous.iter_mut()) {
+            let compressed = (1.0 + COMPRESSION * magnitude).ln();
+            flux += (compressed - *previous).max(0.0);
+            *previous = compressed;
+        }
+        flux
+    }
+
+    /// Check whether the window `POST_MAX` before the latest one is an onset, returning its time
+    /// if it is.
+    fn pick_peak(&mut self) -> Option<f64> {
+        let hop = self.windower.hop_size() as f64 / self.sample_rate as f64;
+        let frames = |seconds: f64| (seconds / hop).ceil() as usize;
+        let (pre_max, post_max, pre_average) =
+            (frames(PRE_MAX), frames(POST_MAX), frames(PRE_AVERAGE));
+
+        while self.flux.len() > pre_average + post_max + 1 {
+            self.flux.pop_front();
+        }
+        let candidate = self.flux.len().checked_sub(post_max + 1)?;
+        let flux = self.flux[candidate];
+
+        let is_max = self
+            .flux
+            .iter()
+            .skip(candidate.saturating_sub(pre_max))
+            .all(|other| *other <= flux);
+        let average = self.flux.iter().sum::<f32>() / self.flux.len() as f32;
+        if !is_max || flux < average + self.threshold {
+            return None;
+        }
+
+        // Report the middle of the window, as that is where the flux is most sensitive.
+        let start = self.flux_end - ((post_max + 1) * self.windower.hop_size()) as u64;
+        let time =
+            (start as f64 + self.windower.window_size() as f64 / 2.0) / self.sample_rate as f64;
+        if matches!(self.last_onset, Some(last) if time - last < self.min_interval) {
+            return None;
+        }
+        self.last_onset = Some(time);
+        Some(time)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: usize = 44_100;
+
+    /// A sung-like tone with a few harmonics, a 5 ms attack, a 20 ms release and 5.5 Hz vibrato
+    /// of `vibrato` cents either way, starting at `onset` seconds.
+    fn note(frequency: f32, vibrato: f32, onset: f64, length: f64, signal: &mut [f32]) {
+        let start = (onset * SAMPLE_RATE as f64) as usize;
+        let end = (((onset + length) * SAMPLE_RATE as f64) as usize).min(signal.len());
+        for (i, sample) in signal[start..end].iter_mut().enumerate() {
+            let t = i as f32 / SAMPLE_RATE as f32;
+            let envelope = (t / 0.005).min((length as f32 - t) / 0.02).min(1.0);
+            let rate = std::f32::consts::TAU * 5.5;
+            let depth = vibrato * std::f32::consts::LN_2 / 1200.0;
+            let phase =
+                std::f32::consts::TAU * frequency * (t + depth * (1.0 - (rate * t).cos()) / rate);
+            *sample += envelope * 0.1 * (phase.sin() + 0.5 * (2.0 * phase).sin());
+        }
+    }
+
+    fn detect(detector: &mut OnsetDetector, signal: &[f32]) -> Vec<f64> {
+        signal
+            .chunks(1_000)
+            .flat_map(|chunk| detector.process(chunk))
+            .collect()
+    }
+
+    #[test]
+    fn note_starts_are_found_after_silence_and_between_notes() {
+        let mut signal = vec![0.0; 2 * SAMPLE_RATE];
+        note(220.0, 0.0, 0.25, 0.5, &mut signal);
+        // Legato into the next note.
+        note(330.0, 0.0, 0.75, 0.5, &mut signal);
+        note(262.0, 0.0, 1.5, 0.4, &mut signal);
+
+        let mut detector = OnsetDetector::new(2048, 512, SAMPLE_RATE, 0.01);
+        let onsets = detect(&mut detector, &signal);
+        assert_eq!(onsets.len(), 3, "{:?}", onsets);
+        for (onset, expected) in onsets.iter().zip(&[0.25, 0.75, 1.5]) {
+            assert!((onset - expected).abs() < 0.02, "{:?}", onsets);
+        }
+
+        // The timestamps start again from zero.
+        detector.reset();
+        let onsets = detect(&mut detector, &signal[SAMPLE_RATE * 13 / 10..]);
+        assert_eq!(onsets.len(), 1, "{:?}", onsets);
+        assert!((onsets[0] - 0.2).abs() < 0.02, "{:?}", onsets);
+    }
+
+    #[test]
+    fn held_notes_and_quiet_noise_have_no_onsets() {
+        // A note with wide vibrato that is already sounding when the detector starts, with
+        // quiet noise after it.
+        let mut signal = vec![0.0; SAMPLE_RATE];
+        note(220.0, 50.0, 0.0, 0.5, &mut signal);
+        let mut state = 1u32;
+        for sample in signal[SAMPLE_RATE / 2..].iter_mut() {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            *sample = 0.001 * ((state >> 8) as f32 / (1u32 << 24) as f32 - 0.5);
+        }
+
+        let mut detector = OnsetDetector::new(2048, 512, SAMPLE_RATE, 0.01);
+        let onsets = detect(&mut detector, &signal);
+        // Only the note being there from the start counts as an onset.
+        assert_eq!(onsets.len(), 1, "{:?}", onsets);
+        assert!(onsets[0] < 0.05, "{:?}", onsets);
+    }
+}
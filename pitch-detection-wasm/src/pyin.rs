@@ -0,0 +1,282 @@
+//! Probabilistic YIN (Mauch and Dixon, 2014). Rather than committing to a single YIN threshold,
+//! every window yields several candidate periods, weighted by how likely each threshold is.
+//! A hidden Markov model over pitch and voicing then chooses between them, so that one bad
+//! window doesn't drop a note or flip it by an octave.
+//!
+//! `get_pitch` has to answer for each window as it arrives, so the model is decoded online from
+//! its forward probabilities rather than with Viterbi over the whole recording.
+
+use pitch_detection::detector::internals::Pitch;
+use pitch_detection::detector::PitchDetector;
+
+use crate::yin::{
+    cumulative_mean_normalized_difference, first_dip, global_minimum, parabolic_interpolation,
+    power,
+};
+
+/// The lowest pitch the model can represent (A1).
+const MIN_FREQUENCY: f32 = 55.0;
+const BINS_PER_SEMITONE: usize = 5;
+/// Five octaves of pitch bins, up to A6.
+const NUM_BINS: usize = 5 * 12 * BINS_PER_SEMITONE;
+/// The furthest the pitch can move between two windows, in bins.
+const MAX_JUMP: usize = 5 * BINS_PER_SEMITONE;
+/// The probability of switching between voiced and unvoiced from one window to the next.
+const VOICING_SWITCH_PROBABILITY: f32 = 0.01;
+/// The posterior probability of being voiced above which a pitch is reported.
+const VOICED_PROBABILITY: f32 = 0.5;
+
+/// Number of YIN thresholds tried on every window, evenly spaced from `0.01` to `1.0`.
+const NUM_THRESHOLDS: usize = 100;
+/// Parameters of the beta distribution over thresholds, which has a mean of `0.15`.
+const BETA_ALPHA: f32 = 2.0;
+const BETA_BETA: f32 = 34.0 / 3.0;
+/// The fraction of a threshold's weight given to the lowest dip when no dip is below it.
+const ABSOLUTE_MINIMUM_WEIGHT: f32 = 0.01;
+
+/// A possible period of the window, with the probability that it is the right one.
+struct Candidate {
+    frequency: f32,
+    clarity: f32,
+    probability: f32,
+}
+
+pub struct PyinDetector {
+    /// Cumulative mean normalized difference for each lag, reused between windows.
+    difference: Vec<f32>,
+    /// Probability of each YIN threshold.
+    threshold_weights: Vec<f32>,
+    /// Probability of the pitch moving by each number of bins from `-MAX_JUMP` to `MAX_JUMP`.
+    pitch_transitions: Vec<f32>,
+    /// Probability of each voiced state, given the windows seen so far.
+    voiced: Vec<f32>,
+    /// Probability of each unvoiced state. Unvoiced states keep track of the last pitch, so
+    /// a note can resume after a short gap without a jump.
+    unvoiced: Vec<f32>,
+}
+
+impl PyinDetector {
+    /// `padding` is accepted so that every detector can be created the same way, but pYIN does
+    /// not zero pad its windows.
+    pub fn new(size: usize, _padding: usize) -> Self {
+        let threshold_weights = normalized(
+            (1..=NUM_THRESHOLDS)
+                .map(|i| {
+                    let threshold = i as f32 / NUM_THRESHOLDS as f32;
+                    threshold.powf(BETA_ALPHA - 1.0) * (1.0 - threshold).powf(BETA_BETA - 1.0)
+                })
+                .collect(),
+        );
+        let pitch_transitions = normalized(
+            (0..=2 * MAX_JUMP)
+                .map(|i| (MAX_JUMP + 1 - (i as isize - MAX_JUMP as isize).unsigned_abs()) as f32)
+                .collect(),
+        );
+
+        let mut detector = PyinDetector {
+            difference: Vec::with_capacity(size / 2),
+            threshold_weights,
+            pitch_transitions,
+            voiced: vec![],
+            unvoiced: vec![],
+        };
+        detector.reset();
+        detector
+    }
+
+    /// Forget the windows seen so far, e.g. before analysing an unrelated recording.
+    pub fn reset(&mut self) {
+        let uniform = 1.0 / (2 * NUM_BINS) as f32;
+        self.voiced = vec![uniform; NUM_BINS];
+        self.unvoiced = vec![uniform; NUM_BINS];
+    }
+
+    /// The candidate periods of `signal`, each weighted by the total probability of the
+    /// thresholds that pick it.
+    fn candidates(&mut self, signal: &[f32], sample_rate: usize) -> Vec<Candidate> {
+        cumulative_mean_normalized_difference(signal, &mut self.difference);
+        let lowest = match global_minimum(&self.difference) {
+            Some(lag) => lag,
+            None => return vec![],
+        };
+
+        let mut weights = vec![0.0; self.difference.len()];
+        for (i, weight) in self.threshold_weights.iter().enumerate() {
+            let threshold = (i + 1) as f32 / NUM_THRESHOLDS as f32;
+            match first_dip(&self.difference, threshold) {
+                Some(lag) => weights[lag] += weight,
+                None => weights[lowest] += weight * ABSOLUTE_MINIMUM_WEIGHT,
+            }
+        }
+
+        weights
+            .iter()
+            .enumerate()
+            .filter(|(_, &weight)| weight > 0.0)
+            .map(|(lag, &weight)| Candidate {
+                frequency: sample_rate as f32 / parabolic_interpolation(&self.difference, lag),
+                clarity: 1.0 - self.difference[lag].min(1.0),
+                probability: weight,
+            })
+            .collect()
+    }
+
+    /// Advance the model by one window whose voiced states have the observation probabilities
+    /// `observed`, with the remaining probability spread over the unvoiced states.
+    fn step(&mut self, observed: &[f32]) {
+        let voiced_total: f32 = observed.iter().sum();
+        let unvoiced_observed = (1.0 - voiced_total).max(0.0) / NUM_BINS as f32;
+
+        let mut voiced = vec![0.0; NUM_BINS];
+        let mut unvoiced = vec![0.0; NUM_BINS];
+        for bin in 0..NUM_BINS {
+            let (mut from_voiced, mut from_unvoiced) = (0.0, 0.0);
+            let start = bin.saturating_sub(MAX_JUMP);
+            let end = (bin + MAX_JUMP).min(NUM_BINS - 1);
+            for previous in start..=end {
+                let transition = self.pitch_transitions[previous + MAX_JUMP - bin];
+                from_voiced += self.voiced[previous] * transition;
+                from_unvoiced += self.unvoiced[previous] * transition;
+            }
+            voiced[bin] = ((1.0 - VOICING_SWITCH_PROBABILITY) * from_voiced
+                + VOICING_SWITCH_PROBABILITY * from_unvoiced)
+                * observed[bin];
+            unvoiced[bin] = (VOICING_SWITCH_PROBABILITY * from_voiced
+                + (1.0 - VOICING_SWITCH_PROBABILITY) * from_unvoiced)
+                * unvoiced_observed;
+        }
+
+        let total: f32 = voiced.iter().chain(&unvoiced).sum();
+        if total > 0.0 && total.is_finite() {
+            self.voiced = voiced.into_iter().map(|p| p / total).collect();
+            self.unvoiced = unvoiced.into_iter().map(|p| p / total).collect();
+        } else {
+            // The observation is impossible under the model, e.g. a jump further than
+            // `MAX_JUMP` straight after a confident note. Start again from the observation.
+            let total = voiced_total + unvoiced_observed * NUM_BINS as f32;
+            self.voiced = observed.iter().map(|p| p / total).collect();
+            self.unvoiced = vec![unvoiced_observed / total; NUM_BINS];
+        }
+    }
+}
+
+impl PitchDetector<f32> for PyinDetector {
+    fn get_pitch(
+        &mut self,
+        signal: &[f32],
+        sample_rate: usize,
+        power_threshold: f32,
+        clarity_threshold: f32,
+    ) -> Option<Pitch<f32>> {
+        let candidates = if power(signal) < power_threshold {
+            vec![]
+        } else {
+            self.candidates(signal, sample_rate)
+        };
+        let candidates: Vec<(usize, Candidate)> = candidates
+            .into_iter()
+            .filter(|candidate| candidate.clarity >= clarity_threshold)
+            .filter_map(|candidate| Some((bin(candidate.frequency)?, candidate)))
+            .collect();
+
+        let mut observed = vec![0.0; NUM_BINS];
+        for (bin, candidate) in &candidates {
+            observed[*bin] += candidate.probability;
+        }
+        self.step(&observed);
+
+        if self.voiced.iter().sum::<f32>() < VOICED_PROBABILITY {
+            return None;
+        }
+        let best_bin = (0..NUM_BINS).max_by(|&a, &b| self.voiced[a].total_cmp(&self.voiced[b]))?;
+        candidates
+            .into_iter()
+            .filter(|(bin, _)| *bin == best_bin)
+            .max_by(|(_, a), (_, b)| a.probability.total_cmp(&b.probability))
+            .map(|(_, candidate)| Pitch {
+                frequency: candidate.frequency,
+                clarity: candidate.clarity,
+            })
+    }
+}
+
+/// The pitch bin closest to `frequency`, if it is within the range of the model.
+fn bin(frequency: f32) -> Option<usize> {
+    let bin = ((frequency / MIN_FREQUENCY).log2() * (12 * BINS_PER_SEMITONE) as f32).round();
+    if bin.is_finite() && bin >= 0.0 && (bin as usize) < NUM_BINS {
+        Some(bin as usize)
+    } else {
+        None
+    }
+}
+
+fn normalized(values: Vec<f32>) -> Vec<f32> {
+    let total: f32 = values.iter().sum();
+    values.into_iter().map(|value| value / total).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(frequency: f32, len: usize, sample_rate: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| {
+                0.5 * (std::f32::consts::TAU * frequency * i as f32 / sample_rate as f32).sin()
+            })
+            .collect()
+    }
+
+    fn voiced_probability(detector: &PyinDetector) -> f32 {
+        detector.voiced.iter().sum()
+    }
+
+    #[test]
+    fn tracks_a_note_across_a_gap() {
+        let mut detector = PyinDetector::new(2048, 1024);
+        let note = sine(330.0, 2048, 44_100);
+        for _ in 0..5 {
+            let pitch = detector.get_pitch(&note, 44_100, 0.1, 0.5).unwrap();
+            assert!((pitch.frequency - 330.0).abs() < 1.0, "{}", pitch.frequency);
+        }
+
+        assert!(detector.get_pitch(&[0.0; 2048], 44_100, 0.1, 0.5).is_none());
+        let pitch = detector.get_pitch(&note, 44_100, 0.1, 0.5).unwrap();
+        assert!((pitch.frequency - 330.0).abs() < 1.0, "{}", pitch.frequency);
+
+        detector.reset();
+        let pitch = detector
+            .get_pitch(&sine(110.0, 2048, 44_100), 44_100, 0.1, 0.5)
+            .unwrap();
+        assert!((pitch.frequency - 110.0).abs() < 1.0, "{}", pitch.frequency);
+    }
+
+    #[test]
+    fn voicing_and_pitch_follow_the_previous_windows() {
+        let mut detector = PyinDetector::new(2048, 1024);
+        let mut weak = vec![0.0; NUM_BINS];
+        weak[100] = 0.3;
+        let mut strong = vec![0.0; NUM_BINS];
+        strong[100] = 1.0;
+
+        // Starting from silence, a weak candidate isn't enough to start a note...
+        detector.step(&vec![0.0; NUM_BINS]);
+        detector.step(&weak);
+        assert!(voiced_probability(&detector) < VOICED_PROBABILITY);
+
+        // ...but once a note has started, it is enough to continue it.
+        detector.step(&strong);
+        detector.step(&weak);
+        assert!(voiced_probability(&detector) > VOICED_PROBABILITY);
+
+        // An equally likely candidate an octave below loses to the current note.
+        let mut ambiguous = vec![0.0; NUM_BINS];
+        ambiguous[100] = 0.5;
+        ambiguous[100 - 12 * BINS_PER_SEMITONE] = 0.5;
+        detector.step(&ambiguous);
+        let best = (0..NUM_BINS)
+            .max_by(|&a, &b| detector.voiced[a].total_cmp(&detector.voiced[b]))
+            .unwrap();
+        assert_eq!(best, 100);
+    }
+}
@@ -0,0 +1,93 @@
+//! The result of detecting the pitch of a window, with the note it is closest to.
+
+use pitch_detection::detector::internals::Pitch;
+use wasm_bindgen::prelude::*;
+
+use crate::music::Tuning;
+
+/// The pitch of a window. When the window is not `voiced`, only `rms` is meaningful and the
+/// other fields are zero or empty.
+#[wasm_bindgen(getter_with_clone)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PitchResult {
+    pub frequency: f32,
+    pub clarity: f32,
+    /// The MIDI note number closest to `frequency` in the detector's tuning.
+    pub midi: u8,
+    /// The name of the closest note with its octave, spelled for the tuning's key, e.g.
+    /// `"C#4"`.
+    pub note_name: String,
+    /// How far `frequency` is from the closest note, in cents.
+    pub cents: f32,
+    /// The root mean square of the window, whether or not it has a pitch.
+    pub rms: f32,
+    pub voiced: bool,
+}
+
+impl PitchResult {
+    pub fn new(pitch: Option<Pitch<f32>>, signal: &[f32], tuning: &Tuning) -> Self {
+        let rms = if signal.is_empty() {
+            0.0
+        } else {
+            (signal.iter().map(|sample| sample * sample).sum::<f32>() / signal.len() as f32).sqrt()
+        };
+
+        match pitch.filter(|pitch| pitch.frequency > 0.0 && pitch.frequency.is_finite()) {
+            Some(pitch) => {
+                let note = tuning.nearest_note(pitch.frequency);
+                PitchResult {
+                    frequency: pitch.frequency,
+                    clarity: pitch.clarity,
+                    midi: note.midi,
+                    note_name: note.name,
+                    cents: note.cents,
+                    rms,
+                    voiced: true,
+                }
+            }
+            None => PitchResult {
+                frequency: 0.0,
+                clarity: 0.0,
+                midi: 0,
+                note_name: String::new(),
+                cents: 0.0,
+                rms,
+                voiced: false,
+            },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::music::Mode;
+
+    #[test]
+    fn results_describe_the_closest_note() {
+        let signal = [0.5, -0.5, 0.5, -0.5];
+        let pitch = |frequency| {
+            Some(Pitch {
+                frequency,
+                clarity: 0.9,
+            })
+        };
+        let result = PitchResult::new(pitch(450.0), &signal, &Tuning::default());
+        assert!(result.voiced);
+        assert_eq!((result.midi, result.note_name.as_str()), (69, "A4"));
+        assert!((result.cents - 38.9).abs() < 0.1);
+        assert_eq!(result.rms, 0.5);
+
+        // The note is named in the tuning, here Baroque pitch in F major.
+        let mut tuning = Tuning::with_reference(415.0).unwrap();
+        tuning.set_key(5, Mode::Major);
+        let result = PitchResult::new(pitch(440.0), &signal, &tuning);
+        assert_eq!((result.midi, result.note_name.as_str()), (70, "Bb4"));
+        assert!((result.cents - 1.3).abs() < 0.1);
+
+        let result = PitchResult::new(None, &signal, &tuning);
+        assert!(!result.voiced);
+        assert_eq!(result.frequency, 0.0);
+        assert_eq!(result.rms, 0.5);
+    }
+}
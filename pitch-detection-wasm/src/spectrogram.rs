@@ -0,0 +1,226 @@
+//! Short-time spectra and chroma of streamed audio, for drawing a scrolling spectrogram and a
+//! chroma wheel, and for finding the key of a song. Chroma folds the spectrum onto the twelve
+//! pitch classes, so that it shows which notes are sounding regardless of octave.
+
+use wasm_bindgen::prelude::*;
+
+use crate::music::frequency_to_midi;
+use crate::spectrum::{Spectrum, WindowFunction};
+use crate::windower::Windower;
+
+/// Number of pitch classes in a chroma vector, starting from C.
+pub const CHROMA_BINS: usize = 12;
+/// The range of frequencies folded into the chroma, in Hz. Below it the bins are too wide to
+/// tell neighbouring semitones apart, and above it there is little but noise and sibilance.
+const MIN_CHROMA_FREQUENCY: f32 = 80.0;
+const MAX_CHROMA_FREQUENCY: f32 = 5000.0;
+
+/// Frames of a spectrogram, with their chroma. Each frame's magnitudes and chroma are stored one
+/// after the other in flat arrays, so they can be handed to JS without an array per frame:
+/// `magnitudes[frame * bins + bin]` and `chroma[frame * 12 + pitch_class]`.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Spectrogram {
+    bins: usize,
+    bin_width: f32,
+    times: Vec<f64>,
+    magnitudes: Vec<f32>,
+    chroma: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl Spectrogram {
+    /// Number of frames.
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    /// Number of frequency bins in each frame, from `0` Hz up to half the sample rate.
+    #[wasm_bindgen(getter)]
+    pub fn bins(&self) -> usize {
+        self.bins
+    }
+
+    /// The width of a frequency bin in Hz.
+    #[wasm_bindgen(getter)]
+    pub fn bin_width(&self) -> f32 {
+        self.bin_width
+    }
+
+    /// Start of each frame's window, in seconds.
+    #[wasm_bindgen(getter)]
+    pub fn times(&self) -> Vec<f64> {
+        self.times.clone()
+    }
+
+    /// Magnitude of each bin, scaled so that a sine's peak has the sine's amplitude.
+    #[wasm_bindgen(getter)]
+    pub fn magnitudes(&self) -> Vec<f32> {
+        self.magnitudes.clone()
+    }
+
+    /// Energy of each pitch class, from C to B, scaled so that the strongest is `1`. Silent
+    /// frames are all `0`.
+    #[wasm_bindgen(getter)]
+    pub fn chroma(&self) -> Vec<f32> {
+        self.chroma.clone()
+    }
+}
+
+#[wasm_bindgen]
+pub struct SpectrogramAnalyzer {
+    spectrum: Spectrum,
+    windower: Windower,
+    sample_rate: usize,
+    /// The pitch class of each frequency bin, if it is folded into the chroma.
+    pitch_classes: Vec<Option<usize>>,
+    frame: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl SpectrogramAnalyzer {
+    /// Analyse windows of `window_size` samples, starting a new window every `hop_size`
+    /// samples. Longer windows resolve lower notes in the chroma but blur fast changes.
+    pub fn new(
+        window_size: usize,
+        hop_size: usize,
+        sample_rate: usize,
+        window: WindowFunction,
+    ) -> Self {
+        let spectrum = Spectrum::with_window(window_size, 0, window);
+        let bin_width = spectrum.bin_width(sample_rate);
+        let pitch_classes = (0..spectrum.bins())
+            .map(|bin| {
+                let frequency = bin as f32 * bin_width;
+                if !(MIN_CHROMA_FREQUENCY..=MAX_CHROMA_FREQUENCY).contains(&frequency) {
+                    return None;
+                }
+                let midi = frequency_to_midi(frequency).round() as i32;
+                Some(midi.rem_euclid(CHROMA_BINS as i32) as usize)
+            })
+            .collect();
+
+        SpectrogramAnalyzer {
+            spectrum,
+            windower: Windower::new(window_size, hop_size),
+            sample_rate,
+            pitch_classes,
+            frame: vec![],
+        }
+    }
+
+    /// Add a chunk of audio of any length, returning the frames it completes. A whole
+    /// recording can be passed in one chunk.
+    pub fn process(&mut self, chunk: &[f32]) -> Spectrogram {
+        self.windower.push(chunk);
+
+        let mut spectrogram = Spectrogram {
+            bins: self.spectrum.bins(),
+            bin_width: self.spectrum.bin_width(self.sample_rate),
+            ..Spectrogram::default()
+        };
+        while let Some((start, window)) = self.windower.next_window() {
+            self.spectrum.magnitudes(window, &mut self.frame);
+
+            let mut chroma = [0.0; CHROMA_BINS];
+            for (magnitude, pitch_class) in self.frame.iter().zip(&self.pitch_classes) {
+                if let Some(pitch_class) = pitch_class {
+                    chroma[*pitch_class] += magnitude * magnitude;
+                }
+            }
+            let max = chroma.iter().cloned().fold(0.0, f32::max);
+            if max > 0.0 {
+                chroma.iter_mut().for_each(|energy| *energy /= max);
+            }
+
+            spectrogram
+                .times
+                .push(start as f64 / self.sample_rate as f64);
+            spectrogram.magnitudes.extend_from_slice(&self.frame);
+            spectrogram.chroma.extend_from_slice(&chroma);
+        }
+        spectrogram
+    }
+
+    /// Forget all audio seen so far, and start the timestamps from zero again.
+    pub fn reset(&mut self) {
+        self.windower.reset();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SAMPLE_RATE: usize = 44_100;
+
+    fn tone(frequencies: &[f32], len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| {
+                let t = i as f32 / SAMPLE_RATE as f32;
+                frequencies
+                    .iter()
+                    .map(|frequency| 0.2 * (std::f32::consts::TAU * frequency * t).sin())
+                    .sum()
+            })
+            .collect()
+    }
+
+    #[test]
+    fn frames_have_a_spectrum_and_chroma() {
+        let mut analyzer = SpectrogramAnalyzer::new(4096, 1024, SAMPLE_RATE, WindowFunction::Hann);
+        let signal = tone(&[440.0], 6_144);
+        let mut spectrogram = analyzer.process(&signal[..5_000]);
+        assert_eq!(spectrogram.len(), 1);
+        let rest = analyzer.process(&signal[5_000..]);
+        assert_eq!(rest.times(), vec![1024.0 / 44_100.0, 2048.0 / 44_100.0]);
+        spectrogram.times.extend(rest.times);
+        spectrogram.magnitudes.extend(rest.magnitudes);
+        spectrogram.chroma.extend(rest.chroma);
+
+        let bins = spectrogram.bins();
+        assert_eq!(bins, 2049);
+        assert_eq!(spectrogram.magnitudes().len(), 3 * bins);
+        assert_eq!(spectrogram.chroma().len(), 3 * CHROMA_BINS);
+        for (frame, magnitudes) in spectrogram.magnitudes.chunks(bins).enumerate() {
+            let peak = (0..bins)
+                .max_by(|&a, &b| magnitudes[a].total_cmp(&magnitudes[b]))
+                .unwrap();
+            assert!((peak as f32 * spectrogram.bin_width() - 440.0).abs() < 11.0);
+            assert!(
+                (magnitudes[peak] - 0.2).abs() < 0.05,
+                "{}",
+                magnitudes[peak]
+            );
+
+            let chroma = &spectrogram.chroma[frame * CHROMA_BINS..(frame + 1) * CHROMA_BINS];
+            assert_eq!(chroma[9], 1.0, "{:?}", chroma);
+            let strong = chroma.iter().filter(|energy| **energy > 0.1).count();
+            assert_eq!(strong, 1, "{:?}", chroma);
+        }
+
+        analyzer.reset();
+        assert!(analyzer
+            .process(&[0.0; 4096])
+            .chroma()
+            .iter()
+            .all(|c| *c == 0.0));
+    }
+
+    #[test]
+    fn chords_show_every_pitch_class() {
+        // C major across three octaves: C3, G4 and E5.
+        let mut analyzer =
+            SpectrogramAnalyzer::new(8192, 8192, SAMPLE_RATE, WindowFunction::Blackman);
+        let spectrogram = analyzer.process(&tone(&[130.81, 392.0, 659.26], 8192));
+        let chroma = spectrogram.chroma();
+        for pitch_class in 0..CHROMA_BINS {
+            let expected = [0, 4, 7].contains(&pitch_class);
+            assert_eq!(chroma[pitch_class] > 0.5, expected, "{:?}", chroma);
+        }
+    }
+}
@@ -0,0 +1,132 @@
+//! Magnitude spectra of windows of audio, for the analyses that work in the frequency domain.
+
+use std::f32::consts::PI;
+use std::sync::Arc;
+
+use realfft::num_complex::Complex;
+use realfft::{RealFftPlanner, RealToComplex};
+use wasm_bindgen::prelude::*;
+
+/// The window applied to each frame before its spectrum is taken. Hann is a good default;
+/// Blackman leaks less between distant bins at the cost of wider peaks, and rectangular gives
+/// the narrowest peaks with the most leakage.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum WindowFunction {
+    Rectangular,
+    Hann,
+    Hamming,
+    Blackman,
+}
+
+impl WindowFunction {
+    fn weight(self, i: usize, size: usize) -> f32 {
+        let x = 2.0 * PI * i as f32 / size as f32;
+        match self {
+            WindowFunction::Rectangular => 1.0,
+            WindowFunction::Hann => 0.5 - 0.5 * x.cos(),
+            WindowFunction::Hamming => 0.54 - 0.46 * x.cos(),
+            WindowFunction::Blackman => 0.42 - 0.5 * x.cos() + 0.08 * (2.0 * x).cos(),
+        }
+    }
+}
+
+pub struct Spectrum {
+    fft: Arc<dyn RealToComplex<f32>>,
+    window: Vec<f32>,
+    /// Scale that makes the magnitude of a sine's peak equal to its amplitude.
+    scale: f32,
+    input: Vec<f32>,
+    output: Vec<Complex<f32>>,
+    scratch: Vec<Complex<f32>>,
+}
+
+impl Spectrum {
+    /// Spectra of windows of `size` samples with a Hann window, zero padded by `padding`
+    /// samples to interpolate between the frequency bins.
+    pub fn new(size: usize, padding: usize) -> Self {
+        Spectrum::with_window(size, padding, WindowFunction::Hann)
+    }
+
+    pub fn with_window(size: usize, padding: usize, window: WindowFunction) -> Self {
+        let size = size.max(1);
+        let fft = RealFftPlanner::<f32>::new().plan_fft_forward(size + padding);
+        let window: Vec<f32> = (0..size).map(|i| window.weight(i, size)).collect();
+        let scale = 2.0 / window.iter().sum::<f32>();
+
+        Spectrum {
+            input: fft.make_input_vec(),
+            output: fft.make_output_vec(),
+            scratch: fft.make_scratch_vec(),
+            fft,
+            window,
+            scale,
+        }
+    }
+
+    /// Number of frequency bins in each spectrum.
+    pub fn bins(&self) -> usize {
+        self.output.len()
+    }
+
+    /// The width of a frequency bin in Hz.
+    pub fn bin_width(&self, sample_rate: usize) -> f32 {
+        sample_rate as f32 / self.input.len() as f32
+    }
+
+    /// Compute the magnitude spectrum of `signal`, which is truncated or zero padded to the
+    /// window size, into `magnitudes`.
+    pub fn magnitudes(&mut self, signal: &[f32], magnitudes: &mut Vec<f32>) {
+        for (i, input) in self.input.iter_mut().enumerate() {
+            *input = match (signal.get(i), self.window.get(i)) {
+                (Some(sample), Some(window)) => sample * window,
+                _ => 0.0,
+            };
+        }
+        self.fft
+            .process_with_scratch(&mut self.input, &mut self.output, &mut self.scratch)
+            .expect("buffers are the size of the FFT");
+
+        magnitudes.clear();
+        magnitudes.extend(self.output.iter().map(|bin| bin.norm() * self.scale));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sine_peaks_have_the_sines_amplitude() {
+        // A sine exactly on bin 64, and one between bins, which leaks further.
+        let sine = |bin: f32| -> Vec<f32> {
+            (0..1024)
+                .map(|i| 0.5 * (2.0 * PI * bin * i as f32 / 1024.0).sin())
+                .collect()
+        };
+        let mut magnitudes = vec![];
+        for &window in [
+            WindowFunction::Rectangular,
+            WindowFunction::Hann,
+            WindowFunction::Hamming,
+            WindowFunction::Blackman,
+        ]
+        .iter()
+        {
+            let mut spectrum = Spectrum::with_window(1024, 0, window);
+            assert_eq!(spectrum.bins(), 513);
+            spectrum.magnitudes(&sine(64.0), &mut magnitudes);
+            assert!((magnitudes[64] - 0.5).abs() < 1e-3, "{:?}", window);
+            assert_eq!(spectrum.bin_width(44_100), 44_100.0 / 1024.0);
+        }
+
+        let mut leakage = |window| {
+            Spectrum::with_window(1024, 0, window).magnitudes(&sine(64.5), &mut magnitudes);
+            magnitudes[100]
+        };
+        let rectangular = leakage(WindowFunction::Rectangular);
+        let hann = leakage(WindowFunction::Hann);
+        let blackman = leakage(WindowFunction::Blackman);
+        assert!(blackman < hann && hann < rectangular);
+    }
+}
@@ -0,0 +1,224 @@
+//! Pitch detection of streamed audio without allocations at the JS boundary. Passing a
+//! `Float32Array` to `get_pitch` copies it into a fresh allocation in WASM memory, and each
+//! result is another allocation; at small hop sizes that costs as much as the detection. The
+//! streaming detector instead owns a ring buffer in WASM memory that JS writes samples into
+//! through a view, and writes its results into a buffer that JS reads through another.
+//!
+//! This only removes the allocations, not the copies. WASM memory is not a
+//! `SharedArrayBuffer`, as that needs a build with atomics and a page served with
+//! cross-origin isolation, so samples are still copied once into the ring buffer by whoever
+//! owns the memory, and results once out of the output buffer.
+//!
+//! From JS, `new Float32Array(memory.buffer, detector.input_ptr(), detector.capacity())` is a
+//! view of the ring buffer. Write up to `writable()` samples starting at `write_offset()`,
+//! wrapping around at the end, and `commit` them. `process_available()` then returns the
+//! number of windows detected, whose `[frequency, clarity]` pairs are at the start of
+//! `new Float32Array(memory.buffer, detector.output_ptr(), 2 * count)`. Views are invalidated
+//! when WASM memory grows, so they should be made again whenever `memory.buffer` changes.
+
+use pitch_detection::detector::PitchDetector;
+use wasm_bindgen::prelude::*;
+
+use crate::{new_detector, pitch_option_to_output, PitchAlgorithm};
+
+#[wasm_bindgen]
+pub struct StreamingDetector {
+    detector: Box<dyn PitchDetector<f32>>,
+    window_size: usize,
+    hop_size: usize,
+    sample_rate: usize,
+    power_threshold: f32,
+    clarity_threshold: f32,
+    ring: Vec<f32>,
+    /// Number of samples committed since the detector started.
+    written: u64,
+    /// Position of the start of the next window in the stream, in samples.
+    next_window: u64,
+    /// The window being detected, unwrapped from the ring buffer.
+    window: Vec<f32>,
+    /// `[frequency, clarity]` of each window found by the last `process_available`.
+    output: Vec<f32>,
+    /// Number of windows detected since the detector started.
+    windows: u64,
+}
+
+#[wasm_bindgen]
+impl StreamingDetector {
+    /// Detect the pitch of windows of `window_size` samples, starting a new window every
+    /// `hop_size` samples. The ring buffer holds `capacity` samples, and at least a window and
+    /// a hop; larger buffers let JS write more at a time between calls to `process_available`.
+    pub fn new(
+        algorithm: PitchAlgorithm,
+        window_size: usize,
+        hop_size: usize,
+        sample_rate: usize,
+        power_threshold: f32,
+        clarity_threshold: f32,
+        capacity: usize,
+    ) -> Self {
+        let hop_size = hop_size.max(1);
+        let capacity = capacity.max(window_size + hop_size);
+        // A full ring buffer holds at most this many windows.
+        let max_windows = (capacity - window_size) / hop_size + 1;
+
+        StreamingDetector {
+            detector: new_detector(algorithm, window_size, window_size / 2),
+            window_size,
+            hop_size,
+            sample_rate,
+            power_threshold,
+            clarity_threshold,
+            ring: vec![0.0; capacity],
+            written: 0,
+            next_window: 0,
+            window: vec![0.0; window_size],
+            output: vec![0.0; 2 * max_windows],
+            windows: 0,
+        }
+    }
+
+    /// Pointer to the ring buffer in WASM memory.
+    pub fn input_ptr(&mut self) -> *mut f32 {
+        self.ring.as_mut_ptr()
+    }
+
+    /// Number of samples in the ring buffer.
+    pub fn capacity(&self) -> usize {
+        self.ring.len()
+    }
+
+    /// Position in the ring buffer that the next sample goes in.
+    pub fn write_offset(&self) -> usize {
+        (self.written % self.ring.len() as u64) as usize
+    }
+
+    /// Number of samples that can be written without overwriting ones that are still needed.
+    pub fn writable(&self) -> usize {
+        let unprocessed = self.written.saturating_sub(self.next_window) as usize;
+        self.ring.len() - unprocessed
+    }
+
+    /// Mark `count` samples, written from `write_offset()`, as part of the stream. At most
+    /// `writable()` samples are taken.
+    pub fn commit(&mut self, count: usize) {
+        self.written += count.min(self.writable()) as u64;
+    }
+
+    /// Detect the pitch of every window that the committed samples complete, returning how
+    /// many there were. Their results are in the output buffer until the next call, with a
+    /// frequency of `-1` if there was no pitch.
+    pub fn process_available(&mut self) -> usize {
+        let capacity = self.ring.len();
+        let mut count = 0;
+        while self.next_window + self.window_size as u64 <= self.written {
+            let start = (self.next_window % capacity as u64) as usize;
+            let first = self.window_size.min(capacity - start);
+            self.window[..first].copy_from_slice(&self.ring[start..start + first]);
+            self.window[first..].copy_from_slice(&self.ring[..self.window_size - first]);
+
+            let pitch = self.detector.get_pitch(
+                &self.window,
+                self.sample_rate,
+                self.power_threshold,
+                self.clarity_threshold,
+            );
+            pitch_option_to_output(pitch, &mut self.output[2 * count..2 * count + 2]);
+            self.next_window += self.hop_size as u64;
+            count += 1;
+        }
+        self.windows += count as u64;
+        count
+    }
+
+    /// Pointer to the output buffer in WASM memory.
+    pub fn output_ptr(&self) -> *const f32 {
+        self.output.as_ptr()
+    }
+
+    /// Number of windows detected since the detector started. Window `i` starts `i * hop_size`
+    /// samples into the stream.
+    pub fn window_count(&self) -> f64 {
+        self.windows as f64
+    }
+
+    /// Forget all audio seen so far.
+    pub fn reset(&mut self) {
+        self.written = 0;
+        self.next_window = 0;
+        self.windows = 0;
+    }
+}
+
+impl StreamingDetector {
+    /// Write as many of `samples` into the ring buffer as fit, returning how many were written.
+    pub fn write(&mut self, samples: &[f32]) -> usize {
+        let count = samples.len().min(self.writable());
+        let offset = self.write_offset();
+        let first = count.min(self.ring.len() - offset);
+        self.ring[offset..offset + first].copy_from_slice(&samples[..first]);
+        self.ring[..count - first].copy_from_slice(&samples[first..count]);
+        self.commit(count);
+        count
+    }
+
+    /// The `[frequency, clarity]` pairs of the windows found by the last `process_available`.
+    pub fn pitches(&self, count: usize) -> &[f32] {
+        &self.output[..2 * count]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::batch::analyze;
+
+    #[test]
+    fn streamed_windows_match_the_whole_buffer() {
+        let signal: Vec<f32> = (0..8_000)
+            .map(|i| {
+                let frequency = if i < 4_000 { 200.0 } else { 300.0 };
+                0.5 * (std::f32::consts::TAU * frequency * i as f32 / 8_000.0).sin()
+            })
+            .collect();
+        let expected = analyze(&signal, 8_000, PitchAlgorithm::Yin, 512, 128, 0.1, 0.8);
+
+        // The ring buffer wraps around many times, and chunks don't line up with windows.
+        let mut detector =
+            StreamingDetector::new(PitchAlgorithm::Yin, 512, 128, 8_000, 0.1, 0.8, 700);
+        let mut pitches = vec![];
+        for mut chunk in signal.chunks(300) {
+            while !chunk.is_empty() {
+                let written = detector.write(chunk);
+                chunk = &chunk[written..];
+                let count = detector.process_available();
+                pitches.extend_from_slice(detector.pitches(count));
+            }
+        }
+
+        assert_eq!(detector.window_count(), expected.len() as f64);
+        for (pitch, frame) in pitches.chunks(2).zip(&expected) {
+            assert_eq!(pitch[0], frame.frequency.unwrap_or(-1.0));
+            assert_eq!(pitch[1], frame.clarity);
+        }
+    }
+
+    #[test]
+    fn unprocessed_samples_are_not_overwritten() {
+        let mut detector =
+            StreamingDetector::new(PitchAlgorithm::McLeod, 256, 64, 8_000, 0.1, 0.8, 100);
+        assert_eq!(detector.capacity(), 320);
+        assert_eq!(detector.write(&[0.0; 1_000]), 320);
+        assert_eq!(detector.writable(), 0);
+        detector.commit(10);
+        assert_eq!(detector.writable(), 0);
+
+        // A full ring buffer holds two windows, and then the samples of two hops can be reused.
+        assert_eq!(detector.process_available(), 2);
+        assert_eq!(detector.pitches(2), [-1.0, 0.0, -1.0, 0.0]);
+        assert_eq!((detector.write_offset(), detector.writable()), (0, 128));
+
+        detector.reset();
+        assert_eq!((detector.write_offset(), detector.writable()), (0, 320));
+        assert_eq!(detector.process_available(), 0);
+    }
+}
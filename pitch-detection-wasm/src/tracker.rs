@@ -0,0 +1,316 @@
+//! A stateful pitch tracker for streamed audio. The detectors look at each window on its own,
+//! so their output jitters and occasionally jumps by an octave. The tracker cuts the stream
+//! into overlapping windows itself and smooths the detected pitches over time.
+
+use std::collections::VecDeque;
+use std::iter::FromIterator;
+
+use pitch_detection::detector::internals::Pitch;
+use pitch_detection::detector::PitchDetector;
+use wasm_bindgen::prelude::*;
+
+use crate::windower::Windower;
+use crate::{new_detector, PitchAlgorithm};
+
+const DEFAULT_MEDIAN_LENGTH: usize = 5;
+const DEFAULT_VOICING_HYSTERESIS: f32 = 0.1;
+/// How close, in semitones, a pitch has to be to an octave above or below the recent pitch to
+/// be treated as an octave error.
+const OCTAVE_TOLERANCE: f32 = 1.0;
+
+/// The smoothed pitch of one window.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PitchFrame {
+    /// Start of the window, in seconds since the tracker started.
+    pub time: f64,
+    /// `None` if the window is unvoiced.
+    pub frequency: Option<f32>,
+    pub clarity: f32,
+}
+
+/// A sequence of pitch frames, split into one array per field so it can be handed to JS
+/// without an object per frame. As with the detectors, a frequency of `-1.0` means there was
+/// no pitch.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PitchContour {
+    times: Vec<f64>,
+    frequencies: Vec<f32>,
+    clarities: Vec<f32>,
+}
+
+#[wasm_bindgen]
+impl PitchContour {
+    pub fn len(&self) -> usize {
+        self.times.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.times.is_empty()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn times(&self) -> Vec<f64> {
+        self.times.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn frequencies(&self) -> Vec<f32> {
+        self.frequencies.clone()
+    }
+
+    #[wasm_bindgen(getter)]
+    pub fn clarities(&self) -> Vec<f32> {
+        self.clarities.clone()
+    }
+}
+
+impl FromIterator<PitchFrame> for PitchContour {
+    fn from_iter<I: IntoIterator<Item = PitchFrame>>(frames: I) -> Self {
+        let mut contour = PitchContour::default();
+        for frame in frames {
+            contour.times.push(frame.time);
+            contour.frequencies.push(frame.frequency.unwrap_or(-1.0));
+            contour.clarities.push(frame.clarity);
+        }
+        contour
+    }
+}
+
+#[wasm_bindgen]
+pub struct PitchTracker {
+    detector: Box<dyn PitchDetector<f32>>,
+    windower: Windower,
+    sample_rate: usize,
+    power_threshold: f32,
+    clarity_threshold: f32,
+    median_length: usize,
+    voicing_hysteresis: f32,
+    voiced: bool,
+    /// The most recent pitches of the current voiced stretch, after octave correction.
+    recent: VecDeque<f32>,
+    /// Number of consecutive windows that have been corrected by an octave.
+    octave_jumps: usize,
+}
+
+#[wasm_bindgen]
+impl PitchTracker {
+    /// Track the pitch of windows of `window_size` samples, starting a new window every
+    /// `hop_size` samples. A window is voiced once its clarity reaches `clarity_threshold`,
+    /// and stays voiced until the clarity drops below the threshold by more than the voicing
+    /// hysteresis.
+    pub fn new(
+        algorithm: PitchAlgorithm,
+        window_size: usize,
+        hop_size: usize,
+        sample_rate: usize,
+        power_threshold: f32,
+        clarity_threshold: f32,
+    ) -> Self {
+        PitchTracker {
+            detector: new_detector(algorithm, window_size, window_size / 2),
+            windower: Windower::new(window_size, hop_size),
+            sample_rate,
+            power_threshold,
+            clarity_threshold,
+            median_length: DEFAULT_MEDIAN_LENGTH,
+            voicing_hysteresis: DEFAULT_VOICING_HYSTERESIS,
+            voiced: false,
+            recent: VecDeque::new(),
+            octave_jumps: 0,
+        }
+    }
+
+    /// Set the number of windows the median filter looks back over. A length of `1` turns the
+    /// filter off.
+    pub fn set_median_length(&mut self, length: usize) {
+        self.median_length = length.max(1);
+        while self.recent.len() > self.median_length {
+            self.recent.pop_front();
+        }
+    }
+
+    pub fn set_voicing_hysteresis(&mut self, hysteresis: f32) {
+        self.voicing_hysteresis = hysteresis.max(0.0);
+    }
+
+    /// Add a chunk of audio of any length, returning the pitch of every window it completes.
+    pub fn process(&mut self, chunk: &[f32]) -> PitchContour {
+        self.push(chunk).into_iter().collect()
+    }
+
+    /// Forget all audio and pitches seen so far, and start the timestamps from zero again.
+    pub fn reset(&mut self) {
+        self.windower.reset();
+        self.voiced = false;
+        self.recent.clear();
+        self.octave_jumps = 0;
+    }
+}
+
+impl PitchTracker {
+    /// Add a chunk of audio of any length, returning the pitch of every window it completes.
+    pub fn push(&mut self, chunk: &[f32]) -> Vec<PitchFrame> {
+        self.windower.push(chunk);
+
+        let mut frames = vec![];
+        while let Some((start, window)) = self.windower.next_window() {
+            let pitch = self.detector.get_pitch(
+                window,
+                self.sample_rate,
+                self.power_threshold,
+                // Clarity is thresholded by `smooth`, with hysteresis.
+                0.0,
+            );
+            let time = start as f64 / self.sample_rate as f64;
+            frames.push(self.smooth(time, pitch));
+        }
+        frames
+    }
+
+    /// Apply voicing hysteresis, octave correction and median filtering to the pitch detected
+    /// in the window starting at `time`.
+    fn smooth(&mut self, time: f64, pitch: Option<Pitch<f32>>) -> PitchFrame {
+        let threshold = if self.voiced {
+            self.clarity_threshold - self.voicing_hysteresis
+        } else {
+            self.clarity_threshold
+        };
+        let pitch = pitch.filter(|pitch| {
+            pitch.clarity >= threshold && pitch.frequency.is_finite() && pitch.frequency > 0.0
+        });
+
+        match pitch {
+            Some(pitch) => {
+                self.voiced = true;
+                let frequency = self.correct_octave(pitch.frequency);
+                self.recent.push_back(frequency);
+                if self.recent.len() > self.median_length {
+                    self.recent.pop_front();
+                }
+                PitchFrame {
+                    time,
+                    frequency: median(&self.recent),
+                    clarity: pitch.clarity,
+                }
+            }
+            None => {
+                self.voiced = false;
+                self.recent.clear();
+                self.octave_jumps = 0;
+                PitchFrame {
+                    time,
+                    frequency: None,
+                    clarity: 0.0,
+                }
+            }
+        }
+    }
+
+    /// Move `frequency` back into the octave of the recent pitches if it is an octave away
+    /// from them. If it stays an octave away for longer than the median filter, the singer
+    /// has really changed octave, and the pitch is left alone.
+    fn correct_octave(&mut self, frequency: f32) -> f32 {
+        let reference = match median(&self.recent) {
+            Some(reference) => reference,
+            None => return frequency,
+        };
+        let semitones = 12.0 * (frequency / reference).log2();
+        let corrected = if (semitones - 12.0).abs() < OCTAVE_TOLERANCE {
+            frequency / 2.0
+        } else if (semitones + 12.0).abs() < OCTAVE_TOLERANCE {
+            frequency * 2.0
+        } else {
+            self.octave_jumps = 0;
+            return frequency;
+        };
+
+        self.octave_jumps += 1;
+        if self.octave_jumps > self.median_length {
+            self.octave_jumps = 0;
+            self.recent.clear();
+            return frequency;
+        }
+        corrected
+    }
+}
+
+fn median(values: &VecDeque<f32>) -> Option<f32> {
+    let mut sorted: Vec<f32> = values.iter().copied().collect();
+    sorted.sort_by(f32::total_cmp);
+    let middle = sorted.len() / 2;
+    match sorted.len() {
+        0 => None,
+        len if len % 2 == 1 => Some(sorted[middle]),
+        _ => Some((sorted[middle - 1] + sorted[middle]) / 2.0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tracker() -> PitchTracker {
+        PitchTracker::new(PitchAlgorithm::Yin, 1024, 256, 8_000, 0.1, 0.7)
+    }
+
+    fn smooth(tracker: &mut PitchTracker, frequency: f32, clarity: f32) -> Option<f32> {
+        tracker
+            .smooth(0.0, Some(Pitch { frequency, clarity }))
+            .frequency
+    }
+
+    #[test]
+    fn chunks_of_any_length_are_split_into_overlapping_windows() {
+        let mut tracker = tracker();
+        let signal: Vec<f32> = (0..4_096)
+            .map(|i| 0.5 * (std::f32::consts::TAU * 200.0 * i as f32 / 8_000.0).sin())
+            .collect();
+
+        let mut frames = vec![];
+        for chunk in signal.chunks(300) {
+            frames.extend(tracker.push(chunk));
+        }
+        // Windows start every 256 samples, as long as the whole window has arrived.
+        assert_eq!(frames.len(), (4_096 - 1_024) / 256 + 1);
+        for (i, frame) in frames.iter().enumerate() {
+            assert_eq!(frame.time, (i * 256) as f64 / 8_000.0);
+            let frequency = frame.frequency.unwrap();
+            assert!((frequency - 200.0).abs() < 1.0, "{}", frequency);
+        }
+
+        tracker.reset();
+        let frames = tracker.push(&signal[..1_024]);
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].time, 0.0);
+    }
+
+    #[test]
+    fn voicing_has_hysteresis() {
+        let mut tracker = tracker();
+        assert_eq!(smooth(&mut tracker, 220.0, 0.65), None);
+        assert_eq!(smooth(&mut tracker, 220.0, 0.75), Some(220.0));
+        // Once voiced, the clarity can drop a little below the threshold.
+        assert_eq!(smooth(&mut tracker, 220.0, 0.65), Some(220.0));
+        assert_eq!(smooth(&mut tracker, 220.0, 0.55), None);
+        assert_eq!(smooth(&mut tracker, 220.0, 0.65), None);
+    }
+
+    #[test]
+    fn octave_errors_and_outliers_are_smoothed_out() {
+        let mut tracker = tracker();
+        for _ in 0..3 {
+            assert_eq!(smooth(&mut tracker, 220.0, 0.9), Some(220.0));
+        }
+        // A single window an octave up, and a single outlier, are filtered out.
+        assert_eq!(smooth(&mut tracker, 440.0, 0.9), Some(220.0));
+        assert_eq!(smooth(&mut tracker, 300.0, 0.9), Some(220.0));
+        assert_eq!(smooth(&mut tracker, 220.0, 0.9), Some(220.0));
+
+        // An octave jump that lasts is followed.
+        let frequencies: Vec<_> = (0..10)
+            .map(|_| smooth(&mut tracker, 440.0, 0.9).unwrap())
+            .collect();
+        assert_eq!(frequencies.last(), Some(&440.0));
+    }
+}
@@ -0,0 +1,274 @@
+//! Vibrato and pitch stability analysis over a stretch of pitch frames, for showing singers how
+//! fast and wide their vibrato is and how steadily they hold a note.
+
+use wasm_bindgen::prelude::*;
+
+use crate::music::frequency_to_midi;
+use crate::tracker::PitchFrame;
+
+/// The range of vibrato rates looked for, in Hz. Sung vibrato is usually around 5 to 7 Hz.
+const MIN_RATE: f64 = 3.0;
+const MAX_RATE: f64 = 10.0;
+/// How periodic the pitch has to be, from `0` to `1`, to count as vibrato.
+const MIN_REGULARITY: f32 = 0.3;
+/// The narrowest vibrato reported, in cents either side of the centre pitch.
+const MIN_EXTENT: f64 = 5.0;
+/// The fewest voiced frames worth analysing.
+const MIN_FRAMES: usize = 8;
+
+/// The vibrato and stability of a stretch of pitch frames.
+#[wasm_bindgen]
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct VibratoAnalysis {
+    /// Vibrato rate in Hz, or `0` if there is no vibrato.
+    pub rate: f32,
+    /// How far the vibrato goes either side of the centre pitch, in cents.
+    pub extent: f32,
+    /// How periodic the vibrato is, from `0` to `1`.
+    pub regularity: f32,
+    /// Standard deviation of the centre pitch, with the vibrato removed, in cents.
+    pub deviation: f32,
+    /// How steadily the centre pitch is held, from `0` (a semitone or more of deviation) to `1`
+    /// (no deviation at all).
+    pub steadiness: f32,
+}
+
+/// Analyse the vibrato of the frames at `times`, in seconds, with the pitches `frequencies`.
+/// Frames with a frequency of zero or less are unvoiced, and are skipped.
+#[wasm_bindgen]
+pub fn analyze_vibrato(times: &[f64], frequencies: &[f32]) -> VibratoAnalysis {
+    let frames: Vec<PitchFrame> = times
+        .iter()
+        .zip(frequencies)
+        .map(|(&time, &frequency)| PitchFrame {
+            time,
+            frequency: Some(frequency).filter(|frequency| *frequency > 0.0),
+            clarity: 0.0,
+        })
+        .collect();
+    analyze(&frames)
+}
+
+pub fn analyze(frames: &[PitchFrame]) -> VibratoAnalysis {
+    let voiced: Vec<(f64, f64)> = frames
+        .iter()
+        .filter_map(|frame| {
+            let frequency = frame.frequency?;
+            Some((frame.time, 100.0 * frequency_to_midi(frequency) as f64))
+        })
+        .collect();
+    if voiced.len() < MIN_FRAMES {
+        return VibratoAnalysis::default();
+    }
+
+    let (step, cents) = resample(&voiced);
+    let trend = linear_trend(&cents);
+    let residual: Vec<f64> = cents.iter().zip(&trend).map(|(c, t)| c - t).collect();
+
+    let vibrato = vibrato_period(&residual, step).and_then(|(period, regularity)| {
+        let smoothed = moving_average(&residual, period.round() as usize);
+        let centre: Vec<f64> = trend.iter().zip(&smoothed).map(|(t, s)| t + s).collect();
+        let vibrato: Vec<f64> = cents.iter().zip(&centre).map(|(c, m)| c - m).collect();
+        let extent = 2f64.sqrt() * standard_deviation(&vibrato, 0.0);
+        // Anything narrower is jitter that happens to be periodic rather than vibrato.
+        if extent < MIN_EXTENT {
+            return None;
+        }
+        Some((1.0 / (period * step), extent, regularity, centre))
+    });
+    let (rate, extent, regularity, centre) = vibrato.unwrap_or((0.0, 0.0, 0.0, cents));
+
+    let mean = centre.iter().sum::<f64>() / centre.len() as f64;
+    let deviation = standard_deviation(&centre, mean);
+
+    VibratoAnalysis {
+        rate: rate as f32,
+        extent: extent as f32,
+        regularity,
+        deviation: deviation as f32,
+        steadiness: (1.0 - deviation / 100.0).clamp(0.0, 1.0) as f32,
+    }
+}
+
+/// Resample `(time, value)` pairs onto an even grid, interpolating over gaps. Returns the
+/// time between samples and the values.
+fn resample(points: &[(f64, f64)]) -> (f64, Vec<f64>) {
+    let mut steps: Vec<f64> = points
+        .windows(2)
+        .map(|pair| pair[1].0 - pair[0].0)
+        .filter(|step| *step > 0.0)
+        .collect();
+    steps.sort_by(f64::total_cmp);
+    let step = steps.get(steps.len() / 2).copied().unwrap_or(1.0);
+
+    let (start, end) = (points[0].0, points[points.len() - 1].0);
+    let len = ((end - start) / step).round() as usize + 1;
+    let mut next = 0;
+    let values = (0..len)
+        .map(|i| {
+            let time = start + i as f64 * step;
+            while next + 1 < points.len() - 1 && points[next + 1].0 <= time {
+                next += 1;
+            }
+            let ((t0, v0), (t1, v1)) = (points[next], points[next + 1]);
+            if t1 > t0 {
+                v0 + (v1 - v0) * ((time - t0) / (t1 - t0)).clamp(0.0, 1.0)
+            } else {
+                v0
+            }
+        })
+        .collect();
+    (step, values)
+}
+
+/// The least squares straight line through evenly spaced `values`.
+fn linear_trend(values: &[f64]) -> Vec<f64> {
+    let n = values.len() as f64;
+    let mean_x = (n - 1.0) / 2.0;
+    let mean_y = values.iter().sum::<f64>() / n;
+    let (mut covariance, mut variance) = (0.0, 0.0);
+    for (x, y) in values.iter().enumerate() {
+        covariance += (x as f64 - mean_x) * (y - mean_y);
+        variance += (x as f64 - mean_x) * (x as f64 - mean_x);
+    }
+    let slope = if variance > 0.0 {
+        covariance / variance
+    } else {
+        0.0
+    };
+    (0..values.len())
+        .map(|x| mean_y + slope * (x as f64 - mean_x))
+        .collect()
+}
+
+/// Find the period of the vibrato in `residual`, in samples, from the highest peak of its
+/// autocorrelation within the range of vibrato rates. Returns the period and the height of the
+/// peak, which is how periodic the vibrato is.
+fn vibrato_period(residual: &[f64], step: f64) -> Option<(f64, f32)> {
+    let min_lag = ((1.0 / (MAX_RATE * step)).floor() as usize).max(1);
+    // At least two periods are needed to see that the pitch repeats.
+    let max_lag = ((1.0 / (MIN_RATE * step)).ceil() as usize).min(residual.len() / 2);
+    if min_lag + 2 > max_lag {
+        return None;
+    }
+
+    let energy = residual.iter().map(|r| r * r).sum::<f64>() / residual.len() as f64;
+    if energy <= 0.0 {
+        return None;
+    }
+    let correlation: Vec<f64> = (0..=max_lag + 1)
+        .map(|lag| {
+            let sum: f64 = residual
+                .iter()
+                .zip(&residual[lag..])
+                .map(|(a, b)| a * b)
+                .sum();
+            sum / (residual.len() - lag) as f64 / energy
+        })
+        .collect();
+
+    let lag = (min_lag..=max_lag)
+        .filter(|&lag| {
+            correlation[lag] > correlation[lag - 1] && correlation[lag] >= correlation[lag + 1]
+        })
+        .max_by(|&a, &b| correlation[a].total_cmp(&correlation[b]))?;
+    let regularity = correlation[lag].min(1.0) as f32;
+    if regularity < MIN_REGULARITY {
+        return None;
+    }
+
+    let (before, at, after) = (correlation[lag - 1], correlation[lag], correlation[lag + 1]);
+    let curvature = before - 2.0 * at + after;
+    let offset = if curvature < 0.0 {
+        (before - after) / (2.0 * curvature)
+    } else {
+        0.0
+    };
+    Some((lag as f64 + offset, regularity))
+}
+
+/// Centred moving average over `width` samples, with a narrower window at the ends.
+fn moving_average(values: &[f64], width: usize) -> Vec<f64> {
+    let half = width.max(1) / 2;
+    (0..values.len())
+        .map(|i| {
+            let window = &values[i.saturating_sub(half)..(i + half + 1).min(values.len())];
+            window.iter().sum::<f64>() / window.len() as f64
+        })
+        .collect()
+}
+
+fn standard_deviation(values: &[f64], mean: f64) -> f64 {
+    (values.iter().map(|v| (v - mean) * (v - mean)).sum::<f64>() / values.len() as f64).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two seconds of frames every 10 ms, with the pitch given in cents relative to A4.
+    fn contour(mut cents: impl FnMut(f64) -> f64) -> Vec<PitchFrame> {
+        (0..200)
+            .map(|i| {
+                let time = i as f64 * 0.01;
+                PitchFrame {
+                    time,
+                    frequency: Some(440.0 * 2f32.powf(cents(time) as f32 / 1200.0)),
+                    clarity: 1.0,
+                }
+            })
+            .collect()
+    }
+
+    #[test]
+    fn vibrato_rate_and_extent_are_measured() {
+        let analysis = analyze(&contour(|t| 50.0 * (std::f64::consts::TAU * 5.5 * t).sin()));
+        assert!((analysis.rate - 5.5).abs() < 0.2, "{:?}", analysis);
+        assert!((analysis.extent - 50.0).abs() < 5.0, "{:?}", analysis);
+        assert!(analysis.regularity > 0.8, "{:?}", analysis);
+        assert!(analysis.steadiness > 0.9, "{:?}", analysis);
+    }
+
+    #[test]
+    fn vibrato_is_measured_through_gaps_and_drift() {
+        // The singer goes 30 cents sharp over the note, and there are a few unvoiced frames.
+        let mut frames = contour(|t| 15.0 * t + 40.0 * (std::f64::consts::TAU * 6.0 * t).sin());
+        for frame in frames[50..55].iter_mut() {
+            frame.frequency = None;
+        }
+        let times: Vec<f64> = frames.iter().map(|frame| frame.time).collect();
+        let frequencies: Vec<f32> = frames
+            .iter()
+            .map(|frame| frame.frequency.unwrap_or(-1.0))
+            .collect();
+
+        let analysis = analyze_vibrato(&times, &frequencies);
+        assert!((analysis.rate - 6.0).abs() < 0.3, "{:?}", analysis);
+        assert!((analysis.extent - 40.0).abs() < 6.0, "{:?}", analysis);
+        assert!(
+            analysis.deviation > 5.0 && analysis.deviation < 15.0,
+            "{:?}",
+            analysis
+        );
+    }
+
+    #[test]
+    fn steady_and_wandering_notes_have_no_vibrato() {
+        // A held note with a little random jitter.
+        let mut state = 1u32;
+        let steady = analyze(&contour(|_| {
+            state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            6.0 * ((state >> 8) as f64 / (1u32 << 24) as f64 - 0.5)
+        }));
+        assert_eq!(steady.rate, 0.0);
+        assert_eq!(steady.extent, 0.0);
+        assert!(steady.steadiness > 0.95, "{:?}", steady);
+
+        // A slow wander up and down by a semitone.
+        let wandering = analyze(&contour(|t| 100.0 * (std::f64::consts::PI * t).sin()));
+        assert_eq!(wandering.rate, 0.0);
+        assert!(wandering.steadiness < 0.5, "{:?}", wandering);
+
+        assert_eq!(analyze(&contour(|_| 0.0)[..4]), VibratoAnalysis::default());
+    }
+}
@@ -0,0 +1,121 @@
+//! Cutting streamed audio into overlapping windows, for the analyses that take chunks of any
+//! length but look at a window at a time.
+
+pub struct Windower {
+    window_size: usize,
+    hop_size: usize,
+    /// Audio that is still needed for upcoming windows.
+    buffer: Vec<f32>,
+    /// Position of the start of `buffer` in the stream, in samples.
+    buffer_start: u64,
+    /// Position of the start of the next window in the stream, in samples.
+    next_window: u64,
+}
+
+impl Windower {
+    /// Cut the stream into windows of `window_size` samples, starting a new window every
+    /// `hop_size` samples.
+    pub fn new(window_size: usize, hop_size: usize) -> Self {
+        Windower {
+            window_size,
+            hop_size: hop_size.max(1),
+            buffer: vec![],
+            buffer_start: 0,
+            next_window: 0,
+        }
+    }
+
+    pub fn window_size(&self) -> usize {
+        self.window_size
+    }
+
+    pub fn hop_size(&self) -> usize {
+        self.hop_size
+    }
+
+    /// Add a chunk of audio of any length. The windows it completes are returned by
+    /// `next_window`.
+    pub fn push(&mut self, chunk: &[f32]) {
+        // Drop the audio before the next window. If the hop is longer than the window, the
+        // next window may start after the end of the buffer.
+        let consumed = (self.next_window - self.buffer_start).min(self.buffer.len() as u64);
+        self.buffer.drain(..consumed as usize);
+        self.buffer_start += consumed;
+        self.buffer.extend_from_slice(chunk);
+    }
+
+    /// The next complete window and the position of its start in the stream, in samples, or
+    /// `None` until more audio is pushed.
+    pub fn next_window(&mut self) -> Option<(u64, &[f32])> {
+        let buffer_end = self.buffer_start + self.buffer.len() as u64;
+        if self.next_window + self.window_size as u64 > buffer_end {
+            return None;
+        }
+        let start = self.next_window;
+        self.next_window += self.hop_size as u64;
+        let offset = (start - self.buffer_start) as usize;
+        Some((start, &self.buffer[offset..offset + self.window_size]))
+    }
+
+    /// Forget all audio seen so far, and start the positions from zero again.
+    pub fn reset(&mut self) {
+        self.buffer.clear();
+        self.buffer_start = 0;
+        self.next_window = 0;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn windows(windower: &mut Windower, chunk: &[f32]) -> Vec<(u64, Vec<f32>)> {
+        windower.push(chunk);
+        let mut windows = vec![];
+        while let Some((start, window)) = windower.next_window() {
+            windows.push((start, window.to_vec()));
+        }
+        windows
+    }
+
+    #[test]
+    fn windows_span_chunks_and_only_keep_the_audio_they_need() {
+        let signal: Vec<f32> = (0..10).map(|i| i as f32).collect();
+        let mut windower = Windower::new(4, 2);
+        assert!(windows(&mut windower, &signal[..3]).is_empty());
+        assert_eq!(
+            windows(&mut windower, &signal[3..7]),
+            vec![(0, vec![0.0, 1.0, 2.0, 3.0]), (2, vec![2.0, 3.0, 4.0, 5.0])]
+        );
+        assert_eq!(
+            windows(&mut windower, &signal[7..]),
+            vec![(4, vec![4.0, 5.0, 6.0, 7.0]), (6, vec![6.0, 7.0, 8.0, 9.0])]
+        );
+        windower.push(&[]);
+        assert_eq!(windower.buffer, vec![8.0, 9.0]);
+
+        windower.reset();
+        assert_eq!(
+            windows(&mut windower, &signal[..4]),
+            vec![(0, signal[..4].to_vec())]
+        );
+    }
+
+    #[test]
+    fn hops_longer_than_the_window_skip_audio() {
+        let signal: Vec<f32> = (0..10).map(|i| i as f32).collect();
+        let mut windower = Windower::new(2, 5);
+        assert_eq!(
+            windows(&mut windower, &signal[..4]),
+            vec![(0, vec![0.0, 1.0])]
+        );
+        assert!(windows(&mut windower, &signal[4..6]).is_empty());
+        assert_eq!(
+            windows(&mut windower, &signal[6..]),
+            vec![(5, vec![5.0, 6.0])]
+        );
+        windower.push(&[]);
+        assert!(windower.buffer.is_empty());
+        assert_eq!(windower.buffer_start, 10);
+    }
+}
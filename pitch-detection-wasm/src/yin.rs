@@ -0,0 +1,153 @@
+//! The YIN pitch detector (de Cheveigné and Kawahara, 2002). YIN looks for the lag at which the
+//! signal best matches a shifted copy of itself, like autocorrelation, but normalizes the
+//! difference so that it makes far fewer octave errors on the voice.
+
+use pitch_detection::detector::internals::Pitch;
+use pitch_detection::detector::PitchDetector;
+
+/// The value of the cumulative mean normalized difference below which a dip is accepted as the
+/// period. This is the threshold suggested in the YIN paper.
+const THRESHOLD: f32 = 0.1;
+
+pub struct YinDetector {
+    /// Cumulative mean normalized difference for each lag, reused between windows.
+    difference: Vec<f32>,
+}
+
+impl YinDetector {
+    /// `padding` is accepted so that every detector can be created the same way, but YIN does
+    /// not zero pad its windows.
+    pub fn new(size: usize, _padding: usize) -> Self {
+        YinDetector {
+            difference: Vec::with_capacity(size / 2),
+        }
+    }
+}
+
+impl PitchDetector<f32> for YinDetector {
+    fn get_pitch(
+        &mut self,
+        signal: &[f32],
+        sample_rate: usize,
+        power_threshold: f32,
+        clarity_threshold: f32,
+    ) -> Option<Pitch<f32>> {
+        if power(signal) < power_threshold {
+            return None;
+        }
+        cumulative_mean_normalized_difference(signal, &mut self.difference);
+
+        // YIN falls back to the lowest dip when none is below the threshold. It will usually
+        // be rejected by the clarity threshold anyway.
+        let lag =
+            first_dip(&self.difference, THRESHOLD).or_else(|| global_minimum(&self.difference))?;
+        let clarity = 1.0 - self.difference[lag].min(1.0);
+        if clarity < clarity_threshold {
+            return None;
+        }
+
+        Some(Pitch {
+            frequency: sample_rate as f32 / parabolic_interpolation(&self.difference, lag),
+            clarity,
+        })
+    }
+}
+
+/// The power of a signal, as used by the detectors in `pitch-detection` to decide whether there
+/// is anything to detect.
+pub(crate) fn power(signal: &[f32]) -> f32 {
+    signal.iter().map(|sample| sample * sample).sum()
+}
+
+/// Compute the cumulative mean normalized difference of the first half of `signal` against
+/// itself shifted by every lag up to half the signal's length, writing it into `output`.
+/// Values near `0.0` mean the signal is close to periodic with that lag.
+pub(crate) fn cumulative_mean_normalized_difference(signal: &[f32], output: &mut Vec<f32>) {
+    let width = signal.len() / 2;
+    output.clear();
+    output.resize(width, 1.0);
+
+    let mut running_sum = 0.0;
+    for lag in 1..width {
+        let difference: f32 = signal[..width]
+            .iter()
+            .zip(&signal[lag..lag + width])
+            .map(|(a, b)| (a - b) * (a - b))
+            .sum();
+        running_sum += difference;
+        output[lag] = if running_sum > 0.0 {
+            difference * lag as f32 / running_sum
+        } else {
+            1.0
+        };
+    }
+}
+
+/// The first lag whose difference is below `threshold`, moved forward to the bottom of the dip
+/// it is in. Lags below 2 are never considered, since the difference is always small there.
+pub(crate) fn first_dip(difference: &[f32], threshold: f32) -> Option<usize> {
+    let mut lag = (2..difference.len()).find(|&lag| difference[lag] < threshold)?;
+    while lag + 1 < difference.len() && difference[lag + 1] < difference[lag] {
+        lag += 1;
+    }
+    Some(lag)
+}
+
+/// The lag with the smallest difference, ignoring lags below 2.
+pub(crate) fn global_minimum(difference: &[f32]) -> Option<usize> {
+    (2..difference.len()).min_by(|&a, &b| difference[a].total_cmp(&difference[b]))
+}
+
+/// Refine the position of the dip at `lag` by fitting a parabola through it and its neighbours.
+pub(crate) fn parabolic_interpolation(values: &[f32], lag: usize) -> f32 {
+    if lag == 0 || lag + 1 >= values.len() {
+        return lag as f32;
+    }
+    let (before, at, after) = (values[lag - 1], values[lag], values[lag + 1]);
+    let curvature = before - 2.0 * at + after;
+    if curvature.abs() < f32::EPSILON {
+        return lag as f32;
+    }
+    lag as f32 + (before - after) / (2.0 * curvature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_the_pitch_of_a_harmonic_tone() {
+        // A 220 Hz tone whose second harmonic is stronger than the fundamental, which tends to
+        // fool autocorrelation into reporting 440 Hz.
+        let signal: Vec<f32> = (0..2048)
+            .map(|i| {
+                let t = i as f32 / 44_100.0;
+                0.3 * (std::f32::consts::TAU * 220.0 * t).sin()
+                    + 0.5 * (std::f32::consts::TAU * 440.0 * t).sin()
+            })
+            .collect();
+        let pitch = YinDetector::new(2048, 1024)
+            .get_pitch(&signal, 44_100, 0.0, 0.5)
+            .unwrap();
+        assert!((pitch.frequency - 220.0).abs() < 1.0, "{}", pitch.frequency);
+        assert!(pitch.clarity > 0.9);
+    }
+
+    #[test]
+    fn quiet_or_noisy_signals_have_no_pitch() {
+        let mut detector = YinDetector::new(1024, 512);
+        assert!(detector
+            .get_pitch(&[0.0; 1024], 44_100, 0.01, 0.5)
+            .is_none());
+
+        // Deterministic white-ish noise.
+        let mut state = 1u32;
+        let noise: Vec<f32> = (0..1024)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                (state >> 8) as f32 / (1u32 << 24) as f32 - 0.5
+            })
+            .collect();
+        assert!(detector.get_pitch(&noise, 44_100, 0.01, 0.8).is_none());
+    }
+}
@@ -0,0 +1,206 @@
+//! Accuracy of the detectors on synthetic signals, run natively with `cargo test`.
+
+#![cfg(not(target_arch = "wasm32"))]
+
+mod signals;
+
+use std::f64::consts::PI;
+
+use pitch_detection_wasm::{
+    AutocorrelationDetector, McLeodDetector, PitchResult, PyinDetector, YinDetector,
+};
+
+const WINDOW_SIZES: [usize; 2] = [1024, 2048];
+const SAMPLE_RATES: [usize; 3] = [22_050, 44_100, 48_000];
+const FREQUENCIES: [f64; 5] = [110.0, 196.0, 330.0, 523.25, 880.0];
+const POWER_THRESHOLD: f32 = 0.01;
+const CLARITY_THRESHOLD: f32 = 0.7;
+
+#[derive(Debug, Clone, Copy)]
+enum Detector {
+    Autocorrelation,
+    McLeod,
+    Yin,
+    Pyin,
+}
+
+const DETECTORS: [Detector; 4] = [
+    Detector::Autocorrelation,
+    Detector::McLeod,
+    Detector::Yin,
+    Detector::Pyin,
+];
+
+impl Detector {
+    /// Detect the pitch of a single window with a fresh detector.
+    fn detect(self, signal: &[f32], sample_rate: usize) -> PitchResult {
+        let (size, padding) = (signal.len(), signal.len() / 2);
+        let (power, clarity) = (POWER_THRESHOLD, CLARITY_THRESHOLD);
+        match self {
+            Detector::Autocorrelation => AutocorrelationDetector::new(size, padding).detect(
+                signal,
+                sample_rate,
+                power,
+                clarity,
+            ),
+            Detector::McLeod => {
+                McLeodDetector::new(size, padding).detect(signal, sample_rate, power, clarity)
+            }
+            Detector::Yin => {
+                YinDetector::new(size, padding).detect(signal, sample_rate, power, clarity)
+            }
+            Detector::Pyin => {
+                PyinDetector::new(size, padding).detect(signal, sample_rate, power, clarity)
+            }
+        }
+    }
+
+    /// The largest error, in cents, allowed on a clean tone with a period of `period` samples
+    /// in a window of `window_size`.
+    fn tolerance(self, period: f64, window_size: usize) -> f64 {
+        match self {
+            // Autocorrelation doesn't interpolate between lags, so it finds the period to the
+            // nearest sample. On top of that, the autocorrelation of a window tapers off as
+            // `1 - lag / window_size`, which pulls the peak of a sine towards shorter lags by
+            // `period² / (4π² (window_size - period))` samples.
+            Detector::Autocorrelation => {
+                let bias = period * period / (4.0 * PI * PI * (window_size as f64 - period));
+                signals::cents(period, period - bias - 0.5)
+            }
+            _ => TOLERANCE,
+        }
+    }
+
+    /// Whether the detector is known to miss a tone with `periods` periods in the window. These
+    /// cases are only checked by `known_misses_are_detected`, which is ignored until they are
+    /// fixed.
+    fn known_miss(self, periods: f64) -> bool {
+        match self {
+            // Because of the taper, the autocorrelation at the period is at most
+            // `1 - 1 / periods` of the power, which is below the clarity threshold in short
+            // windows.
+            Detector::Autocorrelation => periods < 1.0 / (1.0 - CLARITY_THRESHOLD as f64),
+            // McLeod finds no pitch below three periods, and is up to 12 cents off below five.
+            Detector::McLeod => periods < 5.0,
+            Detector::Yin | Detector::Pyin => false,
+        }
+    }
+}
+
+/// The largest error, in cents, allowed on a clean tone, for the detectors that interpolate the
+/// period.
+const TOLERANCE: f64 = 10.0;
+
+/// The fewest periods of the tone a window has to hold for a detector to find it.
+const MIN_PERIODS: f64 = 2.0;
+
+/// Check that every detector finds `expected` in the window made by `signal` for every
+/// window size and sample rate, within the detector's tolerance plus `extra_tolerance` cents.
+/// Only the cases the detectors are known to miss are checked if `known_misses` is set, and
+/// only the others if it isn't.
+fn assert_accurate(
+    name: &str,
+    signal: impl Fn(f64, usize, usize) -> Vec<f32>,
+    extra_tolerance: f64,
+    known_misses: bool,
+) {
+    let mut failures = vec![];
+    for detector in DETECTORS.iter() {
+        for &window_size in WINDOW_SIZES.iter() {
+            for &sample_rate in SAMPLE_RATES.iter() {
+                for &frequency in FREQUENCIES.iter() {
+                    let period = sample_rate as f64 / frequency;
+                    let periods = window_size as f64 / period;
+                    if periods < MIN_PERIODS || detector.known_miss(periods) != known_misses {
+                        continue;
+                    }
+                    let result =
+                        detector.detect(&signal(frequency, sample_rate, window_size), sample_rate);
+                    let error = signals::cents(result.frequency as f64, frequency);
+                    if !result.voiced
+                        || error.abs() > detector.tolerance(period, window_size) + extra_tolerance
+                    {
+                        failures.push(format!(
+                            "{:?} found {} Hz (clarity {}) for {} Hz with a window of {} at {} Hz",
+                            detector,
+                            result.frequency,
+                            result.clarity,
+                            frequency,
+                            window_size,
+                            sample_rate
+                        ));
+                    }
+                }
+            }
+        }
+    }
+    assert!(failures.is_empty(), "{}:\n{}", name, failures.join("\n"));
+}
+
+/// A 5.5 Hz vibrato of ±50 cents moves the pitch during the window, so the detected pitch can
+/// be anywhere within the extent of the vibrato.
+fn voice(frequency: f64, sample_rate: usize, len: usize) -> Vec<f32> {
+    signals::voice(frequency, 5.5, 50.0, 0.1, sample_rate, len)
+}
+
+#[test]
+fn sines_are_detected_accurately() {
+    assert_accurate("sine", signals::sine, 0.0, false);
+}
+
+#[test]
+fn harmonic_tones_are_detected_at_their_fundamental() {
+    assert_accurate("sawtooth", signals::sawtooth, 0.0, false);
+}
+
+#[test]
+fn a_voice_with_vibrato_and_noise_is_detected() {
+    assert_accurate("voice", voice, 50.0, false);
+}
+
+#[test]
+#[ignore = "autocorrelation and McLeod miss tones with only a few periods in the window"]
+fn known_misses_are_detected() {
+    assert_accurate("sine", signals::sine, 0.0, true);
+    assert_accurate("sawtooth", signals::sawtooth, 0.0, true);
+    assert_accurate("voice", voice, 50.0, true);
+}
+
+#[test]
+fn noise_and_silence_are_not_confidently_pitched() {
+    for detector in DETECTORS.iter() {
+        for &window_size in WINDOW_SIZES.iter() {
+            let silence = detector.detect(&vec![0.0; window_size], 44_100);
+            assert!(!silence.voiced, "{:?} found a pitch in silence", detector);
+            assert_eq!(silence.rms, 0.0);
+
+            let noise = detector.detect(&signals::noise(window_size as u64, window_size), 44_100);
+            assert!(
+                !noise.voiced || noise.clarity < 0.8,
+                "{:?} found {} Hz with a clarity of {} in noise",
+                detector,
+                noise.frequency,
+                noise.clarity
+            );
+        }
+    }
+}
+
+#[test]
+fn legacy_output_slice_matches_the_result() {
+    let signal = signals::sine(440.0, 44_100, 2048);
+    let mut output = [0.0; 2];
+    McLeodDetector::new(2048, 1024).get_pitch(&signal, 44_100, 0.01, 0.7, &mut output);
+    let result = McLeodDetector::new(2048, 1024).detect(&signal, 44_100, 0.01, 0.7);
+    assert_eq!(output, [result.frequency, result.clarity]);
+    assert_eq!(result.note_name, "A4");
+
+    // A slice that is too short is filled as far as it goes, rather than panicking.
+    let mut output = [0.0; 1];
+    McLeodDetector::new(2048, 1024).get_pitch(&signal, 44_100, 0.01, 0.7, &mut output);
+    assert_eq!(output, [result.frequency]);
+
+    let mut output = [0.0; 2];
+    YinDetector::new(2048, 1024).get_pitch(&[0.0; 2048], 44_100, 0.01, 0.7, &mut output);
+    assert_eq!(output, [-1.0, 0.0]);
+}
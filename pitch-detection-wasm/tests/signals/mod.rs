@@ -0,0 +1,92 @@
+//! Synthetic test signals, shared by the native tests and benchmarks.
+
+// Not every test uses every signal.
+#![allow(dead_code)]
+
+use std::f64::consts::TAU;
+
+/// A pure sine wave.
+pub fn sine(frequency: f64, sample_rate: usize, len: usize) -> Vec<f32> {
+    harmonic(frequency, &[1.0], sample_rate, len)
+}
+
+/// A tone with the given amplitude for each harmonic, starting with the fundamental.
+pub fn harmonic(frequency: f64, amplitudes: &[f64], sample_rate: usize, len: usize) -> Vec<f32> {
+    let total: f64 = amplitudes.iter().sum();
+    (0..len)
+        .map(|i| {
+            let t = i as f64 / sample_rate as f64;
+            let sample: f64 = amplitudes
+                .iter()
+                .enumerate()
+                .map(|(k, amplitude)| amplitude * (TAU * frequency * (k + 1) as f64 * t).sin())
+                .sum();
+            (0.5 * sample / total) as f32
+        })
+        .collect()
+}
+
+/// A sawtooth-like tone with eight harmonics falling off as `1 / k`.
+pub fn sawtooth(frequency: f64, sample_rate: usize, len: usize) -> Vec<f32> {
+    let amplitudes: Vec<f64> = (1..=8).map(|k| 1.0 / k as f64).collect();
+    harmonic(frequency, &amplitudes, sample_rate, len)
+}
+
+/// A vowel-like tone whose strongest harmonics are the 2nd to 4th, with a vibrato of
+/// `extent` cents either side of `frequency` at `rate` Hz, and white noise `noise` times the
+/// RMS of the tone.
+pub fn voice(
+    frequency: f64,
+    rate: f64,
+    extent: f64,
+    noise: f64,
+    sample_rate: usize,
+    len: usize,
+) -> Vec<f32> {
+    let amplitudes = [0.4, 1.0, 0.8, 0.6, 0.2, 0.1];
+    let total: f64 = amplitudes.iter().sum();
+    let mut phase = 0.0;
+    let mut noise_source = Noise::new(7);
+    (0..len)
+        .map(|i| {
+            let t = i as f64 / sample_rate as f64;
+            let cents = extent * (TAU * rate * t).sin();
+            phase += TAU * frequency * 2f64.powf(cents / 1200.0) / sample_rate as f64;
+            let tone: f64 = amplitudes
+                .iter()
+                .enumerate()
+                .map(|(k, amplitude)| amplitude * (phase * (k + 1) as f64).sin())
+                .sum();
+            // The tone's RMS is roughly 0.25, and uniform noise from -1 to 1 has an RMS of
+            // 1 / sqrt(3).
+            (0.5 * tone / total + noise * 0.25 * 3f64.sqrt() * noise_source.next()) as f32
+        })
+        .collect()
+}
+
+/// White noise from -1 to 1, repeatable for a given seed.
+pub fn noise(seed: u64, len: usize) -> Vec<f32> {
+    let mut noise = Noise::new(seed);
+    (0..len).map(|_| 0.5 * noise.next() as f32).collect()
+}
+
+/// The difference between two frequencies, in cents.
+pub fn cents(actual: f64, expected: f64) -> f64 {
+    1200.0 * (actual / expected).log2()
+}
+
+struct Noise(u64);
+
+impl Noise {
+    fn new(seed: u64) -> Self {
+        Noise(seed.wrapping_mul(0x9E37_79B9_7F4A_7C15) | 1)
+    }
+
+    /// The next sample, from -1 to 1 (xorshift64).
+    fn next(&mut self) -> f64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        (self.0 >> 11) as f64 / (1u64 << 52) as f64 - 1.0
+    }
+}
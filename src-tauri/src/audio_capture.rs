@@ -1,125 +1,1617 @@
 use cpal::{
-    traits::{DeviceTrait, HostTrait, StreamTrait},
-    Stream,
+    traits::{DeviceTrait, HostTrait},
+    FromSample, Sample, SampleFormat, SizedSample, Stream, SupportedStreamConfig, I24,
 };
+use serde::{Deserialize, Serialize};
 use std::{
-    marker::{Send, Sync},
+    path::PathBuf,
     sync::{
         atomic::{AtomicBool, Ordering},
-        Arc, LazyLock, Mutex,
+        Arc, Mutex,
     },
-    time::Duration,
+    thread::JoinHandle,
+    time::{Duration, Instant, SystemTime, UNIX_EPOCH},
 };
-use tauri::{AppHandle, Runtime};
+use tauri::{AppHandle, Emitter, Manager, Runtime, State};
+use tokio::sync::oneshot;
 
-struct SafeStream(Stream);
+mod dsp;
+mod jack_host;
+mod level;
+mod pitch;
+mod recording;
+mod resample;
+mod source;
 
-unsafe impl Send for SafeStream {}
-unsafe impl Sync for SafeStream {}
+use source::AudioSource;
 
-struct State {
-    is_recording: Arc<AtomicBool>,
-    stream: Arc<Mutex<Option<SafeStream>>>,
+/// Number of samples (per channel) in each frame emitted by a capture session
+/// when the frontend doesn't ask for a specific size.
+const DEFAULT_FRAME_SIZE: usize = 2048;
+/// How often a capture session emits `audio:level` events, in milliseconds, when the frontend
+/// doesn't ask for a specific interval.
+const DEFAULT_LEVEL_INTERVAL_MS: u64 = 100;
+/// How many seconds of audio the ring buffer between the cpal callback and the
+/// session thread can hold before samples start being dropped.
+const RING_BUFFER_SECONDS: usize = 2;
+/// File in the app data directory where the audio settings are persisted.
+const SETTINGS_FILE: &str = "audio_settings.json";
+/// Directory in the app data directory where recordings are stored.
+const RECORDINGS_DIR: &str = "recordings";
+/// How often a capture session that lost its input device tries to open it again.
+const RECONNECT_INTERVAL: Duration = Duration::from_millis(500);
+/// How often a capture session that fell back to the default input device checks whether the
+/// selected device is back.
+const DEVICE_CHECK_INTERVAL: Duration = Duration::from_secs(2);
+/// How long an input device can go without producing audio before it is considered lost.
+const DEVICE_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// How a capture session turns device audio into frames.
+struct CaptureOptions {
+    /// Number of samples per channel in each emitted frame.
+    frame_size: usize,
+    channel_map: ChannelMap,
+    /// Sample rate to resample to. `None` keeps the device's sample rate.
+    sample_rate: Option<u32>,
+    /// Filtering applied to every channel before it is framed.
+    preprocessing: Option<dsp::PreprocessingOptions>,
+    /// Run pitch detection on the first channel of every frame.
+    pitch_detection: Option<pitch::PitchDetectionOptions>,
+    /// Whether to send the frames themselves to the webview.
+    emit_frames: bool,
+    /// How often to measure the input level, in milliseconds.
+    level_interval_ms: u64,
+}
+
+/// A long-lived capture session. The audio source is owned by `thread`, which
+/// drains it and emits frames until `shared.stop` is set.
+struct CaptureSession {
+    thread: JoinHandle<()>,
+    /// The format of the emitted audio, or `None` while the session is starting.
+    format: Option<SessionFormat>,
+    shared: Arc<SessionShared>,
+}
+
+/// State shared between a capture session's thread and the commands that control it.
+#[derive(Default)]
+struct SessionShared {
+    stop: AtomicBool,
+    /// Where the session thread writes audio while a recording is in progress.
+    recorder: Mutex<Option<recording::Recorder>>,
+    /// The most recent input level measured by the session thread.
+    level: Mutex<Option<level::InputLevel>>,
+}
+
+/// The format of the audio a capture session produces, after channel mapping and resampling.
+#[derive(Debug, Clone, Copy)]
+struct SessionFormat {
+    sample_rate: u32,
+    channels: u16,
+}
+
+/// Where the capture subsystem is in its lifecycle, as reported by `capture_status`.
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum CaptureStatus {
+    /// Nothing is being captured.
+    #[default]
+    Idle,
+    /// Audio is being captured, by a capture session if `session` is set and otherwise by
+    /// `record_sample`.
+    Recording { session: bool },
+    /// The last capture failed. If `recovering` is set, the capture session is still running
+    /// and resumes once an input device can be opened again.
+    Error { message: String, recovering: bool },
+}
+
+impl CaptureStatus {
+    /// Whether audio is being captured, or a session is waiting to resume capturing.
+    fn is_busy(&self) -> bool {
+        matches!(
+            self,
+            CaptureStatus::Recording { .. }
+                | CaptureStatus::Error {
+                    recovering: true,
+                    ..
+                }
+        )
+    }
 }
 
-impl State {
-    fn new() -> Self {
-        Self {
-            is_recording: Arc::new(AtomicBool::new(false)),
-            stream: Arc::new(Mutex::new(None)),
+/// The audio capture subsystem, managed by Tauri. Only one recording or capture session can
+/// run at a time.
+#[derive(Default)]
+pub struct AudioCapture {
+    settings: Mutex<AudioSettings>,
+    session: Mutex<Option<CaptureSession>>,
+    status: Mutex<CaptureStatus>,
+}
+
+impl AudioCapture {
+    fn settings(&self) -> Result<AudioSettings, String> {
+        Ok(self.settings.lock().map_err(|err| err.to_string())?.clone())
+    }
+
+    fn status(&self) -> Result<CaptureStatus, String> {
+        Ok(self.status.lock().map_err(|err| err.to_string())?.clone())
+    }
+
+    fn set_status(&self, status: CaptureStatus) {
+        if let Ok(mut current) = self.status.lock() {
+            *current = status;
         }
     }
+
+    /// Move to the recording state, unless audio is already being captured.
+    fn begin_capture(&self, session: bool) -> Result<(), String> {
+        let mut status = self.status.lock().map_err(|err| err.to_string())?;
+        if status.is_busy() {
+            return Err("Audio is already being captured.".to_string());
+        }
+        *status = CaptureStatus::Recording { session };
+        Ok(())
+    }
+
+    /// Stop the capture session, if any. The status is reset however stopping goes, so that a
+    /// failure doesn't block later captures.
+    fn stop_session(&self) -> Result<(), String> {
+        let session = self.session.lock().map_err(|err| err.to_string())?.take();
+        let Some(session) = session else {
+            return Ok(());
+        };
+
+        session.shared.stop.store(true, Ordering::SeqCst);
+        let stopped = session
+            .thread
+            .join()
+            .map_err(|_| "Failed to join capture thread".to_string())
+            .and_then(|()| {
+                let recorder = session
+                    .shared
+                    .recorder
+                    .lock()
+                    .map_err(|err| err.to_string())?
+                    .take();
+                recorder.map(|recorder| recorder.finish()).transpose()
+            });
+        self.end_capture(&stopped);
+        stopped.map(|_| ())
+    }
+
+    /// Move back to idle, or to the error state if capturing failed.
+    fn end_capture<T>(&self, result: &Result<T, String>) {
+        self.set_status(match result {
+            Ok(_) => CaptureStatus::Idle,
+            Err(message) => CaptureStatus::Error {
+                message: message.clone(),
+                recovering: false,
+            },
+        });
+    }
+}
+
+/// Audio settings that persist between runs of the app.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AudioSettings {
+    /// Name of the input device to capture from. `None` means the host's default input device.
+    pub input_device: Option<String>,
+    /// JACK port to capture from when running on the JACK host. `None` keeps cpal's automatic
+    /// connection to the system capture ports.
+    #[serde(default)]
+    pub jack_port: Option<String>,
+}
+
+/// An input device and the stream configurations it supports.
+#[derive(Debug, Clone, Serialize)]
+pub struct InputDeviceInfo {
+    pub name: String,
+    pub is_default: bool,
+    pub is_selected: bool,
+    pub configs: Vec<InputConfigInfo>,
 }
-static STATE: LazyLock<Arc<Mutex<State>>> = LazyLock::new(|| Arc::new(Mutex::new(State::new())));
 
-/// Record an audio sample from the default input device for `interval` milliseconds.
+#[derive(Debug, Clone, Serialize)]
+pub struct InputConfigInfo {
+    pub channels: u16,
+    pub min_sample_rate: u32,
+    pub max_sample_rate: u32,
+    pub sample_format: String,
+}
+
+/// How the interleaved channels of an input device are turned into the channels handed to
+/// the frontend. Channel indices are zero-based.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "mode", content = "channels", rename_all = "snake_case")]
+pub enum ChannelMap {
+    /// Average every channel into a single channel.
+    #[default]
+    Average,
+    /// Use a single channel as is.
+    Single(usize),
+    /// Sum a subset of the channels into a single channel.
+    Sum(Vec<usize>),
+    /// Keep every channel, de-interleaved.
+    All,
+}
+
+impl ChannelMap {
+    /// Check that every channel referenced by the map exists on a device with `num_channels` channels.
+    fn validate(&self, num_channels: usize) -> Result<(), String> {
+        let indices: &[usize] = match self {
+            ChannelMap::Single(index) => std::slice::from_ref(index),
+            ChannelMap::Sum(indices) if indices.is_empty() => {
+                return Err("At least one channel must be chosen to sum".to_string())
+            }
+            ChannelMap::Sum(indices) => indices,
+            ChannelMap::Average | ChannelMap::All => &[],
+        };
+        match indices.iter().find(|&&index| index >= num_channels) {
+            Some(index) => Err(format!(
+                "Channel {} does not exist; the input device has {} channel(s)",
+                index, num_channels
+            )),
+            None => Ok(()),
+        }
+    }
+
+    /// Number of channels the map produces from a device with `num_channels` channels.
+    fn output_channels(&self, num_channels: usize) -> usize {
+        match self {
+            ChannelMap::All => num_channels,
+            _ => 1,
+        }
+    }
+
+    /// Split `interleaved` audio with `num_channels` channels into the channels described by the map.
+    fn apply(&self, interleaved: &[f32], num_channels: usize) -> Vec<Vec<f32>> {
+        let frames = interleaved.chunks_exact(num_channels);
+        match self {
+            ChannelMap::Average => vec![frames
+                .map(|frame| frame.iter().sum::<f32>() / num_channels as f32)
+                .collect()],
+            ChannelMap::Single(index) => vec![frames.map(|frame| frame[*index]).collect()],
+            ChannelMap::Sum(indices) => vec![frames
+                .map(|frame| indices.iter().map(|&index| frame[index]).sum())
+                .collect()],
+            ChannelMap::All => (0..num_channels)
+                .map(|channel| {
+                    interleaved
+                        .iter()
+                        .skip(channel)
+                        .step_by(num_channels)
+                        .copied()
+                        .collect()
+                })
+                .collect(),
+        }
+    }
+}
+
+/// A block of audio emitted to the webview as an `audio:frame` event. `channels` holds one
+/// buffer per output channel of the session's [`ChannelMap`].
+#[derive(Debug, Clone, Serialize)]
+pub struct AudioFrame {
+    pub sample_rate: u32,
+    pub channels: Vec<Vec<f32>>,
+}
+
+/// Record an audio sample from the selected input device for `interval` milliseconds.
+/// The device's channels are mixed to mono according to `channel_map`, which defaults to
+/// averaging all channels. If `sample_rate` is given, the audio is resampled to that rate;
+/// otherwise it is returned at the device's rate.
 ///
 /// Returns a tuple `(sample_rate, audio_data)`.
 #[tauri::command]
 pub async fn record_sample<R: Runtime>(
     _app_handle: AppHandle<R>,
+    capture: State<'_, AudioCapture>,
     interval: i32,
+    channel_map: Option<ChannelMap>,
+    sample_rate: Option<u32>,
 ) -> Result<(i32, Vec<f32>), String> {
-    {
-        let state = STATE.lock().map_err(|err| err.to_string())?;
-        if state.is_recording.load(Ordering::SeqCst) {
-            return Err("Recording is already in progress.".to_string());
+    let target_sample_rate = sample_rate;
+    let channel_map = channel_map.unwrap_or_default();
+    if channel_map == ChannelMap::All {
+        return Err(
+            "record_sample returns a single channel. Use a capture session to get all channels."
+                .to_string(),
+        );
+    }
+    capture.begin_capture(false)?;
+    let result = async {
+        let mut source = CpalOpener { capture: &capture }.open()?.source;
+        capture_sample(&mut source, interval, &channel_map, target_sample_rate).await
+    }
+    .await;
+    capture.end_capture(&result);
+
+    let (sample_rate, samples) = result?;
+    Ok((sample_rate as i32, samples))
+}
+
+/// Capture `interval` milliseconds of audio from `source`, mixed to a single channel with
+/// `channel_map` and optionally resampled to `target_sample_rate`.
+///
+/// Returns the sample rate and the audio.
+async fn capture_sample(
+    source: &mut impl AudioSource,
+    interval: i32,
+    channel_map: &ChannelMap,
+    target_sample_rate: Option<u32>,
+) -> Result<(u32, Vec<f32>), String> {
+    let sample_rate = source.sample_rate();
+    let num_channels = source.channels();
+    channel_map.validate(num_channels)?;
+
+    // Sleep for the specified interval
+    async_std::task::sleep(Duration::from_millis(10 + interval.max(0) as u64)).await;
+
+    // We now have the resulting data, but it is interleaved based on the number of channels.
+    let mut resulting_data = Vec::new();
+    source.read(&mut resulting_data)?;
+    let resulting_data: Vec<f32> = channel_map
+        .apply(&resulting_data, num_channels)
+        .swap_remove(0)
+        .into_iter()
+        // Our first few samples might not be any good, so skip the firs 10 samples
+        .skip(10)
+        .collect();
+
+    match target_sample_rate {
+        Some(target_sample_rate) => {
+            let resampled = resample::resample(&[resulting_data], sample_rate, target_sample_rate)?;
+            Ok((target_sample_rate, resampled.concat()))
         }
-        state.is_recording.store(true, Ordering::SeqCst);
+        None => Ok((sample_rate, resulting_data)),
     }
+}
 
-    let host = cpal::default_host();
+/// Start a capture session on the selected input device. Audio is pushed to the webview
+/// as `audio:frame` events containing `frame_size` samples per channel, with no gaps between
+/// consecutive frames. The device's channels are mapped according to `channel_map`, which
+/// defaults to averaging them into a single channel. If `sample_rate` is given, frames are
+/// resampled to that rate so that `frame_size` always covers the same duration.
+///
+/// If `preprocessing` is given, every channel is run through a high-pass filter, noise
+/// reduction and a noise gate (each optional) before being framed. Recordings are not affected.
+///
+/// If `pitch_detection` is given, the pitch of the first channel of every frame is detected on
+/// the capture thread and emitted as an `audio:pitch` event. Set `emit_frames` to `false` to only
+/// receive the pitches. The YIN detectors are only available in the browser, and are rejected.
+///
+/// The level of the input device is measured every `level_interval` milliseconds (100 by
+/// default) and emitted as an `audio:level` event.
+///
+/// Returns the sample rate of the emitted frames.
+#[tauri::command]
+#[allow(clippy::too_many_arguments)]
+pub async fn start_capture<R: Runtime>(
+    app_handle: AppHandle<R>,
+    capture: State<'_, AudioCapture>,
+    frame_size: Option<usize>,
+    channel_map: Option<ChannelMap>,
+    sample_rate: Option<u32>,
+    preprocessing: Option<dsp::PreprocessingOptions>,
+    pitch_detection: Option<pitch::PitchDetectionOptions>,
+    emit_frames: Option<bool>,
+    level_interval: Option<u64>,
+) -> Result<u32, String> {
+    // Only one session can be starting or running at a time, so the session lock doesn't need
+    // to be held while the stream starts.
+    capture.begin_capture(true)?;
 
-    // Set up the input device and stream with the default input config.
-    let device = host
-        .default_input_device()
-        .ok_or("No default input device available")?;
+    let options = CaptureOptions {
+        frame_size: frame_size.unwrap_or(DEFAULT_FRAME_SIZE).max(1),
+        channel_map: channel_map.unwrap_or_default(),
+        sample_rate,
+        preprocessing,
+        pitch_detection,
+        emit_frames: emit_frames.unwrap_or(true),
+        level_interval_ms: level_interval.unwrap_or(DEFAULT_LEVEL_INTERVAL_MS).max(1),
+    };
+    // cpal streams can't be moved between threads on every platform, so the stream is
+    // created on the session thread, which reports back once it is playing.
+    let (ready_tx, ready_rx) = oneshot::channel();
+    let shared = Arc::new(SessionShared::default());
+    let thread = std::thread::spawn({
+        let shared = shared.clone();
+        move || run_capture_session(app_handle, options, shared, ready_tx)
+    });
+    // The session is stored while it starts, so that `stop_capture` can stop it meanwhile.
+    *capture.session.lock().map_err(|err| err.to_string())? = Some(CaptureSession {
+        thread,
+        format: None,
+        shared: shared.clone(),
+    });
 
-    let config = device
-        .default_input_config()
+    let started = ready_rx
+        .await
+        .unwrap_or_else(|_| Err("Capture thread exited before starting".to_string()));
+    let mut session = capture.session.lock().map_err(|err| err.to_string())?;
+    if !matches!(&*session, Some(current) if Arc::ptr_eq(&current.shared, &shared)) {
+        return Err("Capture was stopped before it started".to_string());
+    }
+    match started {
+        Ok(format) => {
+            if let Some(current) = session.as_mut() {
+                current.format = Some(format);
+            }
+            Ok(format.sample_rate)
+        }
+        Err(err) => {
+            // The session thread has already returned.
+            if let Some(failed) = session.take() {
+                let _ = failed.thread.join();
+            }
+            capture.set_status(CaptureStatus::Error {
+                message: err.clone(),
+                recovering: false,
+            });
+            Err(err)
+        }
+    }
+}
+
+/// Stop the running capture session, if any, including one that is still starting.
+#[tauri::command]
+pub async fn stop_capture(capture: State<'_, AudioCapture>) -> Result<(), String> {
+    capture.stop_session()
+}
+
+/// Where the capture subsystem is in its lifecycle: idle, recording, or failed. A capture
+/// session that loses its input device reports an error while it tries to reconnect.
+#[tauri::command]
+pub async fn capture_status(capture: State<'_, AudioCapture>) -> Result<CaptureStatus, String> {
+    capture.status()
+}
+
+/// The most recent input level measured by the running capture session, or `None` if no
+/// session is running or it hasn't measured a full interval yet.
+#[tauri::command]
+pub async fn get_input_level(
+    capture: State<'_, AudioCapture>,
+) -> Result<Option<level::InputLevel>, String> {
+    let session = capture.session.lock().map_err(|err| err.to_string())?;
+    let Some(session) = session.as_ref() else {
+        return Ok(None);
+    };
+    let level = *session.shared.level.lock().map_err(|err| err.to_string())?;
+    Ok(level)
+}
+
+/// Start writing the audio of the running capture session to a WAV file in the recordings
+/// directory. The file is named after `song_key`, the key of the song being sung, and the
+/// current time.
+///
+/// Returns the file name of the new recording.
+#[tauri::command]
+pub async fn start_recording<R: Runtime>(
+    app_handle: AppHandle<R>,
+    capture: State<'_, AudioCapture>,
+    song_key: Option<String>,
+) -> Result<String, String> {
+    let session = capture.session.lock().map_err(|err| err.to_string())?;
+    let session = session
+        .as_ref()
+        .ok_or("A capture session must be running to record.")?;
+    let format = session
+        .format
+        .ok_or("The capture session is still starting.")?;
+    let mut recorder = session
+        .shared
+        .recorder
+        .lock()
         .map_err(|err| err.to_string())?;
-    // println!("Using device: {}", device.name().unwrap_or_default());
-    // println!("Using config: {:?}", &config);
-
-    let sample_rate = config.sample_rate().0 as usize;
-    let num_channels = config.channels() as usize;
-    // Buffer to store the captured audio data
-    let buffer = Arc::new(Mutex::new(Vec::with_capacity(
-        sample_rate * num_channels * interval as usize / 1000 * 2,
-    )));
-
-    let err_fn = move |err: cpal::StreamError| {
-        eprintln!("an error occurred on stream: {}", err);
+    if recorder.is_some() {
+        return Err("A recording is already in progress.".to_string());
+    }
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_err(|err| err.to_string())?
+        .as_secs();
+    let new_recorder = recording::Recorder::create(
+        &recordings_dir(&app_handle)?,
+        song_key.as_deref(),
+        timestamp,
+        format.channels,
+        format.sample_rate,
+    )?;
+    let file_name = new_recorder.file_name().to_string();
+    *recorder = Some(new_recorder);
+
+    Ok(file_name)
+}
+
+/// Stop the recording in progress, if any.
+///
+/// Returns the file name of the finished recording.
+#[tauri::command]
+pub async fn stop_recording(capture: State<'_, AudioCapture>) -> Result<Option<String>, String> {
+    let session = capture.session.lock().map_err(|err| err.to_string())?;
+    let Some(session) = session.as_ref() else {
+        return Ok(None);
     };
+    let recorder = session
+        .shared
+        .recorder
+        .lock()
+        .map_err(|err| err.to_string())?
+        .take();
+    recorder.map(|recorder| recorder.finish()).transpose()
+}
 
-    let stream = match config.sample_format() {
-        cpal::SampleFormat::I8 | cpal::SampleFormat::I16 | cpal::SampleFormat::I32 => {
-            return Err("Unsupported sample format".to_string())
-        }
-        cpal::SampleFormat::F32 => {
-            // Clone the Arc before we move it into the closure
-            let buffer = buffer.clone();
-            device
-                .build_input_stream(
-                    &config.into(),
-                    move |data: &[f32], _: &_| {
-                        let mut buf = buffer.lock().map_err(|err| err.to_string()).unwrap();
-                        buf.extend_from_slice(data);
-                    },
-                    err_fn,
-                    None,
-                )
-                .map_err(|err| err.to_string())?
+/// List past recordings, newest first.
+#[tauri::command]
+pub async fn list_recordings<R: Runtime>(
+    app_handle: AppHandle<R>,
+) -> Result<Vec<recording::RecordingInfo>, String> {
+    recording::list_recordings(&recordings_dir(&app_handle)?)
+}
+
+/// Delete a past recording by file name.
+#[tauri::command]
+pub async fn delete_recording<R: Runtime>(
+    app_handle: AppHandle<R>,
+    file_name: String,
+) -> Result<(), String> {
+    recording::delete_recording(&recordings_dir(&app_handle)?, &file_name)
+}
+
+fn recordings_dir<R: Runtime>(app_handle: &AppHandle<R>) -> Result<PathBuf, String> {
+    let app_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|err| err.to_string())?;
+    Ok(app_dir.join(RECORDINGS_DIR))
+}
+
+/// List the input devices available on the host along with the configurations they support.
+#[tauri::command]
+pub async fn list_input_devices(
+    capture: State<'_, AudioCapture>,
+) -> Result<Vec<InputDeviceInfo>, String> {
+    let host = jack_host::audio_host();
+    let default_name = host
+        .default_input_device()
+        .and_then(|device| device.name().ok());
+    let selected_name = capture.settings()?.input_device;
+
+    let mut devices = vec![];
+    for device in host.input_devices().map_err(|err| err.to_string())? {
+        // Devices that can't report a name can't be selected later, so skip them.
+        let Ok(name) = device.name() else {
+            continue;
+        };
+        let configs = device
+            .supported_input_configs()
+            .map(|configs| {
+                configs
+                    .map(|config| InputConfigInfo {
+                        channels: config.channels(),
+                        min_sample_rate: config.min_sample_rate().0,
+                        max_sample_rate: config.max_sample_rate().0,
+                        sample_format: config.sample_format().to_string(),
+                    })
+                    .collect()
+            })
+            .unwrap_or_default();
+        devices.push(InputDeviceInfo {
+            is_default: default_name.as_ref() == Some(&name),
+            is_selected: selected_name.as_ref() == Some(&name),
+            name,
+            configs,
+        });
+    }
+
+    Ok(devices)
+}
+
+/// Select the input device to capture from by name and persist the choice. Passing `None`
+/// goes back to using the host's default input device.
+///
+/// The new device is used the next time a recording or capture session is started.
+#[tauri::command]
+pub async fn set_input_device<R: Runtime>(
+    app_handle: AppHandle<R>,
+    capture: State<'_, AudioCapture>,
+    name: Option<String>,
+) -> Result<(), String> {
+    if let Some(name) = &name {
+        find_input_device(name)?.ok_or_else(|| format!("Input device not found: {}", name))?;
+    }
+
+    let settings = {
+        let mut settings = capture.settings.lock().map_err(|err| err.to_string())?;
+        settings.input_device = name;
+        settings.clone()
+    };
+    save_settings(&app_handle, &settings)
+}
+
+/// List the JACK ports that can be captured from. Fails if the app was built without the
+/// `jack` feature or no JACK server is running.
+#[tauri::command]
+pub async fn list_jack_ports() -> Result<Vec<String>, String> {
+    jack_host::list_ports()
+}
+
+/// Select the JACK port to capture from and persist the choice. Passing `None` goes back to
+/// cpal's automatic connection to the system capture ports.
+///
+/// The port is connected the next time a recording or capture session is started.
+#[tauri::command]
+pub async fn set_jack_port<R: Runtime>(
+    app_handle: AppHandle<R>,
+    capture: State<'_, AudioCapture>,
+    port: Option<String>,
+) -> Result<(), String> {
+    if let Some(port) = &port {
+        let available_ports = jack_host::list_ports()?;
+        if !available_ports.contains(port) {
+            return Err(format!("JACK port not found: {}", port));
         }
-        _ => return Err("Unsupported sample format".to_string()),
+    }
+
+    let settings = {
+        let mut settings = capture.settings.lock().map_err(|err| err.to_string())?;
+        settings.jack_port = port;
+        settings.clone()
     };
+    save_settings(&app_handle, &settings)
+}
 
-    stream.play().map_err(|err| err.to_string())?;
+/// Load the persisted audio settings from the app data directory. Missing or unreadable
+/// settings leave the defaults in place.
+pub fn load_settings<R: Runtime>(app_handle: &AppHandle<R>) -> Result<(), String> {
+    let path = settings_path(app_handle)?;
+    if !path.exists() {
+        return Ok(());
+    }
+    let contents = std::fs::read_to_string(&path).map_err(|err| err.to_string())?;
+    let settings: AudioSettings = serde_json::from_str(&contents).map_err(|err| err.to_string())?;
+    *app_handle
+        .state::<AudioCapture>()
+        .settings
+        .lock()
+        .map_err(|err| err.to_string())? = settings;
 
-    // Sleep for the specified interval
-    async_std::task::sleep(Duration::from_millis(10 + interval as u64)).await;
+    Ok(())
+}
+
+fn save_settings<R: Runtime>(
+    app_handle: &AppHandle<R>,
+    settings: &AudioSettings,
+) -> Result<(), String> {
+    let path = settings_path(app_handle)?;
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(|err| err.to_string())?;
+    }
+    let contents = serde_json::to_string_pretty(settings).map_err(|err| err.to_string())?;
+    std::fs::write(path, contents).map_err(|err| err.to_string())
+}
+
+fn settings_path<R: Runtime>(app_handle: &AppHandle<R>) -> Result<PathBuf, String> {
+    let app_dir = app_handle
+        .path()
+        .app_data_dir()
+        .map_err(|err| err.to_string())?;
+    Ok(app_dir.join(SETTINGS_FILE))
+}
+
+/// Find an input device on the default host by name.
+fn find_input_device(name: &str) -> Result<Option<cpal::Device>, String> {
+    let mut devices = jack_host::audio_host()
+        .input_devices()
+        .map_err(|err| err.to_string())?;
+    Ok(devices.find(|device| device.name().is_ok_and(|device_name| device_name == name)))
+}
 
-    // Stop the stream
-    let state = STATE.lock().map_err(|err| err.to_string())?;
-    if !state.is_recording.load(Ordering::SeqCst) {
-        return Err("No recording in progress.".to_string());
+/// The input device chosen with `set_input_device`, or the default input device if none was
+/// chosen or the chosen device is no longer available.
+///
+/// Returns the device and whether it is standing in for an unavailable chosen device.
+fn selected_input_device(settings: &AudioSettings) -> Result<(cpal::Device, bool), String> {
+    if let Some(name) = &settings.input_device {
+        match find_input_device(name)? {
+            Some(device) => return Ok((device, false)),
+            None => eprintln!(
+                "Selected input device '{}' is not available. Using the default input device.",
+                name
+            ),
+        }
     }
-    state.is_recording.store(false, Ordering::SeqCst);
-    if let Some(stream) = state.stream.lock().map_err(|err| err.to_string())?.take() {
-        drop(stream.0);
+
+    let device = jack_host::audio_host()
+        .default_input_device()
+        .ok_or_else(|| "No default input device available".to_string())?;
+    Ok((device, settings.input_device.is_some()))
+}
+
+/// Connect the JACK port chosen with `set_jack_port` to `device`. Failures are only logged, since
+/// the device is still connected to the system capture ports.
+fn connect_selected_jack_port(device: &cpal::Device, settings: &AudioSettings) {
+    let Some(port) = &settings.jack_port else {
+        return;
+    };
+    let Ok(client_name) = device.name() else {
+        return;
+    };
+    if let Err(err) = jack_host::connect_port(&client_name, port) {
+        eprintln!("Failed to connect JACK port '{}': {}", port, err);
     }
+}
 
-    // We now have the resulting data, but it is interleaved based on the number of channels.
-    let resulting_data = buffer.lock().map_err(|err| err.to_string())?;
-    let resulting_data = resulting_data
-        .chunks(num_channels)
-        .map(|chunk| {
-            // Average the channels to get a single channel output
-            chunk.iter().sum::<f32>() / num_channels as f32
+/// Payload of the `audio:device-lost` event.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceLost {
+    pub device: String,
+    pub message: String,
+}
+
+/// Payload of the `audio:device-connected` event, emitted when a capture session reconnects.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeviceConnected {
+    pub device: String,
+    /// Whether this is the default input device standing in for the unavailable selected device.
+    pub fallback: bool,
+}
+
+/// Where a capture session sends its events.
+trait EventSink {
+    fn send_event<S: Serialize + Clone>(&self, event: &str, payload: S);
+}
+
+impl<R: Runtime> EventSink for AppHandle<R> {
+    fn send_event<S: Serialize + Clone>(&self, event: &str, payload: S) {
+        if let Err(err) = self.emit(event, payload) {
+            eprintln!("Failed to emit {}: {}", event, err);
+        }
+    }
+}
+
+/// An audio source opened for a capture session.
+struct OpenedSource<S> {
+    source: S,
+    /// Name of the device the source captures from.
+    device: String,
+    /// Whether the device is the default input device standing in for the unavailable selected
+    /// device.
+    fallback: bool,
+}
+
+/// Opens the audio source a capture session reads from, so that the session can reopen it when
+/// the device is lost.
+trait SourceOpener {
+    type Source: AudioSource;
+
+    /// Open the selected input device, or the default input device if it isn't available.
+    fn open(&mut self) -> Result<OpenedSource<Self::Source>, String>;
+
+    /// Whether the selected input device is available.
+    fn selected_available(&mut self) -> bool;
+}
+
+/// Opens the input device selected in the capture subsystem's settings.
+struct CpalOpener<'a> {
+    capture: &'a AudioCapture,
+}
+
+impl SourceOpener for CpalOpener<'_> {
+    type Source = source::CpalSource;
+
+    fn open(&mut self) -> Result<OpenedSource<Self::Source>, String> {
+        let settings = self.capture.settings()?;
+        let (device, fallback) = selected_input_device(&settings)?;
+        let source = source::CpalSource::open(&device)?;
+        connect_selected_jack_port(&device, &settings);
+
+        Ok(OpenedSource {
+            source,
+            device: device.name().unwrap_or_default(),
+            fallback,
         })
-        // Our first few samples might not be any good, so skip the firs 10 samples
-        .skip(10)
-        .collect();
+    }
+
+    fn selected_available(&mut self) -> bool {
+        let Ok(AudioSettings {
+            input_device: Some(name),
+            ..
+        }) = self.capture.settings()
+        else {
+            return false;
+        };
+        find_input_device(&name).is_ok_and(|device| device.is_some())
+    }
+}
+
+/// An open audio source, along with what's needed to turn its audio into the session's format.
+struct SessionInput<S> {
+    source: S,
+    device: String,
+    fallback: bool,
+    num_channels: usize,
+    resampler: Option<resample::Resampler>,
+    level_meter: level::LevelMeter,
+    /// When the source last produced audio.
+    last_audio: Instant,
+}
+
+impl<S: AudioSource> SessionInput<S> {
+    fn new(
+        opened: OpenedSource<S>,
+        channel_map: &ChannelMap,
+        format: SessionFormat,
+        level_interval_ms: u64,
+    ) -> Result<Self, String> {
+        let OpenedSource {
+            source,
+            device,
+            fallback,
+        } = opened;
+        let num_channels = source.channels();
+        channel_map.validate(num_channels)?;
+        // A session keeps its format when it reconnects, so a replacement device has to fit it.
+        if channel_map.output_channels(num_channels) != format.channels as usize {
+            return Err(format!(
+                "The input device has {} channel(s), but the capture session needs {}",
+                num_channels, format.channels
+            ));
+        }
+        let resampler = if source.sample_rate() != format.sample_rate {
+            Some(resample::Resampler::new(
+                source.sample_rate(),
+                format.sample_rate,
+                format.channels as usize,
+            )?)
+        } else {
+            None
+        };
+        let level_meter =
+            level::LevelMeter::new(source.sample_rate(), num_channels, level_interval_ms);
+
+        Ok(Self {
+            source,
+            device,
+            fallback,
+            num_channels,
+            resampler,
+            level_meter,
+            last_audio: Instant::now(),
+        })
+    }
+}
+
+/// Body of the capture session thread.
+fn run_capture_session<R: Runtime>(
+    app_handle: AppHandle<R>,
+    options: CaptureOptions,
+    shared: Arc<SessionShared>,
+    ready: oneshot::Sender<Result<SessionFormat, String>>,
+) {
+    let capture = app_handle.state::<AudioCapture>();
+    let opener = CpalOpener { capture: &capture };
+    capture_loop(&app_handle, &capture, opener, options, &shared, ready);
+}
+
+/// Open an input with `opener`, then move audio from it to `events` one frame at a time until
+/// `shared.stop` is set.
+///
+/// If the device is lost, an `audio:device-lost` event is sent and the session keeps trying to
+/// reopen an input, falling back to the default input device if the selected one is gone. While
+/// on the fallback, the session switches back to the selected device once it returns.
+fn capture_loop<O: SourceOpener>(
+    events: &impl EventSink,
+    capture: &AudioCapture,
+    mut opener: O,
+    options: CaptureOptions,
+    shared: &SessionShared,
+    ready: oneshot::Sender<Result<SessionFormat, String>>,
+) {
+    let CaptureOptions {
+        frame_size,
+        channel_map,
+        sample_rate: target_sample_rate,
+        preprocessing,
+        pitch_detection,
+        emit_frames,
+        level_interval_ms,
+    } = options;
+
+    let opened = opener.open().and_then(|opened| {
+        let format = SessionFormat {
+            sample_rate: target_sample_rate.unwrap_or(opened.source.sample_rate()),
+            channels: channel_map.output_channels(opened.source.channels()) as u16,
+        };
+        let input = SessionInput::new(opened, &channel_map, format, level_interval_ms)?;
+        // The detectors aren't `Send`, so they have to be created on this thread.
+        let pitch_tracker = pitch_detection
+            .map(|options| pitch::PitchTracker::new(options, frame_size, format.sample_rate))
+            .transpose()?;
+        Ok((input, format, pitch_tracker))
+    });
+    let (input, format, mut pitch_tracker) = match opened {
+        Ok((input, format, pitch_tracker)) => {
+            let _ = ready.send(Ok(format));
+            (input, format, pitch_tracker)
+        }
+        Err(err) => {
+            let _ = ready.send(Err(err));
+            return;
+        }
+    };
+    let mut input = Some(input);
+    let mut next_attempt = Instant::now();
+    let mut next_check = Instant::now() + DEVICE_CHECK_INTERVAL;
+    let mut preprocessors: Option<Vec<dsp::Preprocessor>> = preprocessing.map(|options| {
+        (0..format.channels)
+            .map(|_| dsp::Preprocessor::new(&options, format.sample_rate))
+            .collect()
+    });
+
+    // Mapped (and possibly resampled) audio that doesn't fill a whole frame yet.
+    let mut pending = vec![Vec::new(); format.channels as usize];
+    while !shared.stop.load(Ordering::SeqCst) {
+        let Some(current) = &mut input else {
+            if Instant::now() >= next_attempt {
+                next_attempt = Instant::now() + RECONNECT_INTERVAL;
+                match opener.open().and_then(|opened| {
+                    SessionInput::new(opened, &channel_map, format, level_interval_ms)
+                }) {
+                    Ok(reopened) => {
+                        events.send_event(
+                            "audio:device-connected",
+                            DeviceConnected {
+                                device: reopened.device.clone(),
+                                fallback: reopened.fallback,
+                            },
+                        );
+                        capture.set_status(CaptureStatus::Recording { session: true });
+                        next_check = Instant::now() + DEVICE_CHECK_INTERVAL;
+                        input = Some(reopened);
+                    }
+                    Err(err) => eprintln!("Failed to reopen the input device: {}", err),
+                }
+            }
+            std::thread::sleep(Duration::from_millis(5));
+            continue;
+        };
+
+        // Switch back to the selected device once it returns.
+        if current.fallback && Instant::now() >= next_check {
+            next_check = Instant::now() + DEVICE_CHECK_INTERVAL;
+            if opener.selected_available() {
+                input = None;
+                next_attempt = Instant::now();
+                continue;
+            }
+        }
+
+        let mut interleaved = Vec::new();
+        let lost = match current.source.read(&mut interleaved) {
+            Err(err) => Some(err),
+            Ok(_) if !interleaved.is_empty() => {
+                current.last_audio = Instant::now();
+                None
+            }
+            Ok(_) if current.last_audio.elapsed() >= DEVICE_TIMEOUT => {
+                Some("The input device stopped producing audio".to_string())
+            }
+            Ok(_) => None,
+        };
+        if let Some(message) = lost {
+            eprintln!("Lost input device '{}': {}", current.device, message);
+            events.send_event(
+                "audio:device-lost",
+                DeviceLost {
+                    device: current.device.clone(),
+                    message: message.clone(),
+                },
+            );
+            capture.set_status(CaptureStatus::Error {
+                message,
+                recovering: true,
+            });
+            input = None;
+            next_attempt = Instant::now();
+            continue;
+        }
+        if interleaved.is_empty() {
+            std::thread::sleep(Duration::from_millis(5));
+            continue;
+        }
+        // Levels are measured before channel mapping so that clipping on any channel shows up.
+        for input_level in current.level_meter.process(&interleaved) {
+            events.send_event("audio:level", input_level);
+            if let Ok(mut level) = shared.level.lock() {
+                *level = Some(input_level);
+            }
+        }
+        let mut channels = channel_map.apply(&interleaved, current.num_channels);
+        if let Some(resampler) = &mut current.resampler {
+            channels = match resampler.process(&channels) {
+                Ok(channels) => channels,
+                Err(err) => {
+                    eprintln!("Failed to resample audio: {}", err);
+                    continue;
+                }
+            };
+        }
+        let recorded = match shared.recorder.lock() {
+            Ok(mut recorder) => recorder
+                .as_mut()
+                .map_or(Ok(()), |recorder| recorder.write(&channels)),
+            Err(err) => Err(err.to_string()),
+        };
+        if let Err(err) = recorded {
+            eprintln!("Failed to write recording: {}", err);
+        }
+        if let Some(preprocessors) = &mut preprocessors {
+            channels = preprocessors
+                .iter_mut()
+                .zip(&channels)
+                .map(|(preprocessor, channel)| preprocessor.process(channel))
+                .collect();
+        }
+        for (pending, channel) in pending.iter_mut().zip(channels) {
+            pending.extend(channel);
+        }
+
+        while pending[0].len() >= frame_size {
+            let frame = AudioFrame {
+                sample_rate: format.sample_rate,
+                channels: pending
+                    .iter_mut()
+                    .map(|pending| pending.drain(..frame_size).collect())
+                    .collect(),
+            };
+            if let Some(pitch_tracker) = &mut pitch_tracker {
+                events.send_event("audio:pitch", pitch_tracker.detect(&frame.channels[0]));
+            }
+            if emit_frames {
+                events.send_event("audio:frame", &frame);
+            }
+        }
+    }
+}
+
+/// Build an input stream for `config`, whatever its sample format, that hands `on_data`
+/// interleaved samples normalized to `f32` in the range `-1.0..=1.0`.
+fn build_f32_input_stream<D, E>(
+    device: &cpal::Device,
+    config: &SupportedStreamConfig,
+    on_data: D,
+    err_fn: E,
+) -> Result<Stream, String>
+where
+    D: FnMut(&[f32]) + Send + 'static,
+    E: FnMut(cpal::StreamError) + Send + 'static,
+{
+    match config.sample_format() {
+        SampleFormat::I8 => {
+            build_converting_input_stream::<i8, _, _>(device, config, on_data, err_fn)
+        }
+        SampleFormat::I16 => {
+            build_converting_input_stream::<i16, _, _>(device, config, on_data, err_fn)
+        }
+        SampleFormat::I24 => {
+            build_converting_input_stream::<I24, _, _>(device, config, on_data, err_fn)
+        }
+        SampleFormat::I32 => {
+            build_converting_input_stream::<i32, _, _>(device, config, on_data, err_fn)
+        }
+        SampleFormat::I64 => {
+            build_converting_input_stream::<i64, _, _>(device, config, on_data, err_fn)
+        }
+        SampleFormat::U8 => {
+            build_converting_input_stream::<u8, _, _>(device, config, on_data, err_fn)
+        }
+        SampleFormat::U16 => {
+            build_converting_input_stream::<u16, _, _>(device, config, on_data, err_fn)
+        }
+        SampleFormat::U32 => {
+            build_converting_input_stream::<u32, _, _>(device, config, on_data, err_fn)
+        }
+        SampleFormat::U64 => {
+            build_converting_input_stream::<u64, _, _>(device, config, on_data, err_fn)
+        }
+        SampleFormat::F32 => {
+            build_converting_input_stream::<f32, _, _>(device, config, on_data, err_fn)
+        }
+        SampleFormat::F64 => {
+            build_converting_input_stream::<f64, _, _>(device, config, on_data, err_fn)
+        }
+        format => Err(format!("Unsupported sample format: {}", format)),
+    }
+}
+
+fn build_converting_input_stream<T, D, E>(
+    device: &cpal::Device,
+    config: &SupportedStreamConfig,
+    mut on_data: D,
+    err_fn: E,
+) -> Result<Stream, String>
+where
+    T: SizedSample,
+    f32: FromSample<T>,
+    D: FnMut(&[f32]) + Send + 'static,
+    E: FnMut(cpal::StreamError) + Send + 'static,
+{
+    // Reused between callbacks so that we only allocate when the device hands us a bigger buffer.
+    let mut converted = Vec::new();
+    device
+        .build_input_stream(
+            &config.config(),
+            move |data: &[T], _: &_| {
+                converted.clear();
+                converted.extend(samples_to_f32(data));
+                on_data(&converted);
+            },
+            err_fn,
+            None,
+        )
+        .map_err(|err| err.to_string())
+}
+
+/// Convert raw samples of any cpal sample type into `f32` samples in the range `-1.0..=1.0`.
+/// Unsigned formats are re-centred so that their midpoint maps to `0.0`.
+fn samples_to_f32<T>(data: &[T]) -> impl Iterator<Item = f32> + '_
+where
+    T: Sample,
+    f32: FromSample<T>,
+{
+    data.iter().map(|sample| sample.to_sample::<f32>())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(actual: &[f32], expected: &[f32]) {
+        assert_eq!(actual.len(), expected.len());
+        for (a, e) in actual.iter().zip(expected) {
+            assert!(
+                (a - e).abs() < 1e-4,
+                "expected {:?}, got {:?}",
+                expected,
+                actual
+            );
+        }
+    }
+
+    #[test]
+    fn converts_signed_integer_samples() {
+        let i8s: Vec<f32> = samples_to_f32(&[i8::MIN, 0, 64]).collect();
+        assert_close(&i8s, &[-1.0, 0.0, 0.5]);
 
-    Ok((sample_rate as i32, resulting_data))
+        let i16s: Vec<f32> = samples_to_f32(&[i16::MIN, -16384, 0, 16384]).collect();
+        assert_close(&i16s, &[-1.0, -0.5, 0.0, 0.5]);
+
+        let i24s: Vec<f32> =
+            samples_to_f32(&[I24::new_unchecked(-(1 << 23)), I24::new_unchecked(1 << 22)])
+                .collect();
+        assert_close(&i24s, &[-1.0, 0.5]);
+
+        let i32s: Vec<f32> = samples_to_f32(&[i32::MIN, 0, 1 << 30]).collect();
+        assert_close(&i32s, &[-1.0, 0.0, 0.5]);
+
+        let i64s: Vec<f32> = samples_to_f32(&[i64::MIN, 0, 1 << 62]).collect();
+        assert_close(&i64s, &[-1.0, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn converts_unsigned_integer_samples() {
+        let u8s: Vec<f32> = samples_to_f32(&[0u8, 128, 192]).collect();
+        assert_close(&u8s, &[-1.0, 0.0, 0.5]);
+
+        let u16s: Vec<f32> = samples_to_f32(&[0u16, 32768, 49152]).collect();
+        assert_close(&u16s, &[-1.0, 0.0, 0.5]);
+
+        let u32s: Vec<f32> = samples_to_f32(&[0u32, 1 << 31, 3 << 30]).collect();
+        assert_close(&u32s, &[-1.0, 0.0, 0.5]);
+
+        let u64s: Vec<f32> = samples_to_f32(&[0u64, 1 << 63, 3 << 62]).collect();
+        assert_close(&u64s, &[-1.0, 0.0, 0.5]);
+    }
+
+    #[test]
+    fn converts_float_samples() {
+        let f32s: Vec<f32> = samples_to_f32(&[-1.0f32, 0.25, 1.0]).collect();
+        assert_close(&f32s, &[-1.0, 0.25, 1.0]);
+
+        let f64s: Vec<f32> = samples_to_f32(&[-1.0f64, 0.25, 1.0]).collect();
+        assert_close(&f64s, &[-1.0, 0.25, 1.0]);
+    }
+
+    #[test]
+    fn channel_map_deinterleaves_and_mixes() {
+        // Three channels: a "vocal" ramp, silence and a constant "guitar".
+        let interleaved = [0.1, 0.0, 0.5, 0.2, 0.0, 0.5, 0.3, 0.0, 0.5];
+
+        assert_eq!(
+            ChannelMap::Single(0).apply(&interleaved, 3),
+            vec![vec![0.1, 0.2, 0.3]]
+        );
+        assert_eq!(
+            ChannelMap::Sum(vec![0, 2]).apply(&interleaved, 3),
+            vec![vec![0.6, 0.7, 0.8]]
+        );
+        assert_eq!(
+            ChannelMap::All.apply(&interleaved, 3),
+            vec![
+                vec![0.1, 0.2, 0.3],
+                vec![0.0, 0.0, 0.0],
+                vec![0.5, 0.5, 0.5]
+            ]
+        );
+        let averaged = ChannelMap::Average.apply(&interleaved, 3);
+        assert_close(&averaged[0], &[0.2, 0.7 / 3.0, 0.8 / 3.0]);
+    }
+
+    #[test]
+    fn channel_map_rejects_missing_channels() {
+        assert!(ChannelMap::Single(1).validate(2).is_ok());
+        assert!(ChannelMap::Single(2).validate(2).is_err());
+        assert!(ChannelMap::Sum(vec![0, 3]).validate(2).is_err());
+        assert!(ChannelMap::Sum(vec![]).validate(2).is_err());
+        assert!(ChannelMap::All.validate(1).is_ok());
+    }
+
+    #[test]
+    fn capture_sample_maps_and_resamples_a_synthetic_source() {
+        // A stereo source with a 440 Hz tone on the right channel and noise on the left.
+        let interleaved: Vec<f32> = (0..48_000)
+            .flat_map(|i| {
+                let tone = (i as f32 * std::f32::consts::TAU * 440.0 / 48_000.0).sin();
+                let noise = ((i * 7919) % 101) as f32 / 101.0 - 0.5;
+                [noise, tone]
+            })
+            .collect();
+        let mut source = source::SyntheticSource::new(interleaved, 48_000, 2);
+
+        let (sample_rate, samples) = async_std::task::block_on(capture_sample(
+            &mut source,
+            200,
+            &ChannelMap::Single(1),
+            Some(16_000),
+        ))
+        .unwrap();
+        assert_eq!(sample_rate, 16_000);
+        // At least the 200 ms that were asked for.
+        assert!(
+            (3_200..6_000).contains(&samples.len()),
+            "captured {} samples",
+            samples.len()
+        );
+        let periods = samples
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count();
+        let frequency = periods as f32 / (samples.len() as f32 / 16_000.0);
+        assert!((frequency - 440.0).abs() < 15.0, "frequency {}", frequency);
+    }
+
+    #[test]
+    fn capture_sample_rejects_missing_channels() {
+        let mut source = source::SyntheticSource::new(vec![0.0; 1_000], 8_000, 1);
+        let result = async_std::task::block_on(capture_sample(
+            &mut source,
+            10,
+            &ChannelMap::Single(1),
+            None,
+        ));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn failed_capture_does_not_leave_the_subsystem_busy() {
+        let capture = AudioCapture::default();
+        capture.begin_capture(false).unwrap();
+        assert!(capture.begin_capture(true).is_err());
+
+        capture.end_capture::<()>(&Err("The input device is no longer available".to_string()));
+        assert_eq!(
+            capture.status().unwrap(),
+            CaptureStatus::Error {
+                message: "The input device is no longer available".to_string(),
+                recovering: false,
+            }
+        );
+        capture.begin_capture(true).unwrap();
+        assert_eq!(
+            capture.status().unwrap(),
+            CaptureStatus::Recording { session: true }
+        );
+
+        // A session that is trying to reconnect still owns the input.
+        capture.set_status(CaptureStatus::Error {
+            message: "The input device is no longer available".to_string(),
+            recovering: true,
+        });
+        assert!(capture.begin_capture(false).is_err());
+        capture.end_capture(&Ok(()));
+        assert_eq!(capture.status().unwrap(), CaptureStatus::Idle);
+    }
+
+    #[test]
+    fn stopping_a_failed_session_does_not_leave_the_subsystem_busy() {
+        let capture = AudioCapture::default();
+        assert_eq!(capture.stop_session(), Ok(()));
+
+        capture.begin_capture(true).unwrap();
+        *capture.session.lock().unwrap() = Some(CaptureSession {
+            thread: std::thread::spawn(|| panic!("capture thread failed")),
+            format: None,
+            shared: Arc::new(SessionShared::default()),
+        });
+        assert!(capture.stop_session().is_err());
+        assert!(matches!(
+            capture.status().unwrap(),
+            CaptureStatus::Error {
+                recovering: false,
+                ..
+            }
+        ));
+        assert!(capture.session.lock().unwrap().is_none());
+        capture.begin_capture(true).unwrap();
+    }
+
+    /// A source that plays a sine until its device is unplugged.
+    struct MockSource {
+        inner: source::SyntheticSource,
+        plugged_in: Arc<AtomicBool>,
+    }
+
+    impl AudioSource for MockSource {
+        fn sample_rate(&self) -> u32 {
+            self.inner.sample_rate()
+        }
+
+        fn channels(&self) -> usize {
+            self.inner.channels()
+        }
+
+        fn read(&mut self, buffer: &mut Vec<f32>) -> Result<usize, String> {
+            if !self.plugged_in.load(Ordering::SeqCst) {
+                return Err("Device unplugged".to_string());
+            }
+            self.inner.read(buffer)
+        }
+    }
+
+    /// Opens a mono 48 kHz "USB mic" while it is plugged in. Otherwise opens a stereo 44.1 kHz
+    /// default device, if there is one.
+    struct MockOpener {
+        plugged_in: Arc<AtomicBool>,
+        has_default: bool,
+    }
+
+    impl SourceOpener for MockOpener {
+        type Source = MockSource;
+
+        fn open(&mut self) -> Result<OpenedSource<MockSource>, String> {
+            let (sample_rate, channels, device, fallback) =
+                if self.plugged_in.load(Ordering::SeqCst) {
+                    (48_000, 1, "USB mic", false)
+                } else if self.has_default {
+                    (44_100, 2, "Default", true)
+                } else {
+                    return Err("No default input device available".to_string());
+                };
+            let inner = source::SyntheticSource::sine(
+                440.0,
+                0.5,
+                Duration::from_secs(10),
+                sample_rate,
+                channels,
+            );
+            Ok(OpenedSource {
+                source: MockSource {
+                    inner,
+                    // Only the USB mic can be unplugged.
+                    plugged_in: if fallback {
+                        Arc::new(AtomicBool::new(true))
+                    } else {
+                        self.plugged_in.clone()
+                    },
+                },
+                device: device.to_string(),
+                fallback,
+            })
+        }
+
+        fn selected_available(&mut self) -> bool {
+            self.plugged_in.load(Ordering::SeqCst)
+        }
+    }
+
+    #[derive(Default)]
+    struct RecordedEvents(Mutex<Vec<(String, serde_json::Value)>>);
+
+    impl RecordedEvents {
+        fn named(&self, event: &str) -> Vec<serde_json::Value> {
+            self.0
+                .lock()
+                .unwrap()
+                .iter()
+                .filter(|(name, _)| name == event)
+                .map(|(_, payload)| payload.clone())
+                .collect()
+        }
+    }
+
+    impl EventSink for RecordedEvents {
+        fn send_event<S: Serialize + Clone>(&self, event: &str, payload: S) {
+            let payload = serde_json::to_value(payload).unwrap();
+            self.0.lock().unwrap().push((event.to_string(), payload));
+        }
+    }
+
+    fn wait_until(condition: impl Fn() -> bool) {
+        let deadline = Instant::now() + Duration::from_secs(10);
+        while !condition() {
+            assert!(Instant::now() < deadline, "timed out");
+            std::thread::sleep(Duration::from_millis(10));
+        }
+    }
+
+    /// Run a capture session on a [`MockOpener`] until `shared.stop` is set.
+    fn spawn_mock_session(
+        opener: MockOpener,
+        capture: &Arc<AudioCapture>,
+        events: &Arc<RecordedEvents>,
+        shared: &Arc<SessionShared>,
+    ) -> (SessionFormat, JoinHandle<()>) {
+        let options = CaptureOptions {
+            frame_size: 1024,
+            channel_map: ChannelMap::Average,
+            sample_rate: None,
+            preprocessing: None,
+            pitch_detection: None,
+            emit_frames: true,
+            level_interval_ms: DEFAULT_LEVEL_INTERVAL_MS,
+        };
+        capture.begin_capture(true).unwrap();
+        let (ready_tx, ready_rx) = oneshot::channel();
+        let thread = std::thread::spawn({
+            let capture = capture.clone();
+            let events = events.clone();
+            let shared = shared.clone();
+            move || capture_loop(&*events, &capture, opener, options, &shared, ready_tx)
+        });
+        (ready_rx.blocking_recv().unwrap().unwrap(), thread)
+    }
+
+    #[test]
+    fn capture_sessions_reject_pitch_detectors_that_only_run_in_the_browser() {
+        let capture = AudioCapture::default();
+        let events = RecordedEvents::default();
+        let opener = MockOpener {
+            plugged_in: Arc::new(AtomicBool::new(true)),
+            has_default: false,
+        };
+        let options = CaptureOptions {
+            frame_size: 1024,
+            channel_map: ChannelMap::Average,
+            sample_rate: None,
+            preprocessing: None,
+            pitch_detection: Some(pitch::PitchDetectionOptions {
+                algorithm: pitch::PitchAlgorithm::Pyin,
+                power_threshold: 0.01,
+                clarity_threshold: 0.7,
+                padding: None,
+            }),
+            emit_frames: true,
+            level_interval_ms: DEFAULT_LEVEL_INTERVAL_MS,
+        };
+        let (ready_tx, mut ready_rx) = oneshot::channel();
+        let shared = SessionShared::default();
+        capture_loop(&events, &capture, opener, options, &shared, ready_tx);
+        let err = ready_rx.try_recv().unwrap().unwrap_err();
+        assert!(err.contains("only available in the browser"), "{}", err);
+    }
+
+    #[test]
+    fn capture_session_falls_back_to_the_default_device_and_back() {
+        let plugged_in = Arc::new(AtomicBool::new(true));
+        let capture = Arc::new(AudioCapture::default());
+        let events = Arc::new(RecordedEvents::default());
+        let shared = Arc::new(SessionShared::default());
+        let opener = MockOpener {
+            plugged_in: plugged_in.clone(),
+            has_default: true,
+        };
+        let (format, thread) = spawn_mock_session(opener, &capture, &events, &shared);
+        assert_eq!(format.sample_rate, 48_000);
+        assert_eq!(format.channels, 1);
+        wait_until(|| events.named("audio:frame").len() >= 2);
+
+        plugged_in.store(false, Ordering::SeqCst);
+        wait_until(|| !events.named("audio:device-connected").is_empty());
+        let lost = events.named("audio:device-lost");
+        assert_eq!(lost.len(), 1);
+        assert_eq!(lost[0]["device"], "USB mic");
+        assert_eq!(
+            events.named("audio:device-connected")[0],
+            serde_json::json!({ "device": "Default", "fallback": true })
+        );
+        assert_eq!(
+            capture.status().unwrap(),
+            CaptureStatus::Recording { session: true }
+        );
+
+        // Frames keep coming at the session's sample rate, resampled from the default device.
+        let frames = events.named("audio:frame").len();
+        wait_until(|| events.named("audio:frame").len() >= frames + 2);
+        assert_eq!(
+            events.named("audio:frame").last().unwrap()["sample_rate"],
+            48_000
+        );
+
+        plugged_in.store(true, Ordering::SeqCst);
+        wait_until(|| events.named("audio:device-connected").len() >= 2);
+        assert_eq!(
+            events.named("audio:device-connected")[1],
+            serde_json::json!({ "device": "USB mic", "fallback": false })
+        );
+        // Switching back to the selected device isn't a loss.
+        assert_eq!(events.named("audio:device-lost").len(), 1);
+
+        shared.stop.store(true, Ordering::SeqCst);
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn capture_session_reports_an_error_until_a_device_returns() {
+        let plugged_in = Arc::new(AtomicBool::new(true));
+        let capture = Arc::new(AudioCapture::default());
+        let events = Arc::new(RecordedEvents::default());
+        let shared = Arc::new(SessionShared::default());
+        let opener = MockOpener {
+            plugged_in: plugged_in.clone(),
+            has_default: false,
+        };
+        let (_, thread) = spawn_mock_session(opener, &capture, &events, &shared);
+        wait_until(|| !events.named("audio:frame").is_empty());
+
+        plugged_in.store(false, Ordering::SeqCst);
+        wait_until(|| !events.named("audio:device-lost").is_empty());
+        // Give the session a few reconnection attempts.
+        std::thread::sleep(RECONNECT_INTERVAL * 3);
+        assert_eq!(
+            capture.status().unwrap(),
+            CaptureStatus::Error {
+                message: "Device unplugged".to_string(),
+                recovering: true,
+            }
+        );
+        assert!(events.named("audio:device-connected").is_empty());
+
+        plugged_in.store(true, Ordering::SeqCst);
+        wait_until(|| !events.named("audio:device-connected").is_empty());
+        assert_eq!(
+            capture.status().unwrap(),
+            CaptureStatus::Recording { session: true }
+        );
+
+        shared.stop.store(true, Ordering::SeqCst);
+        thread.join().unwrap();
+    }
+
+    #[test]
+    fn full_scale_integer_sine_matches_float_sine() {
+        let sine: Vec<f64> = (0..64)
+            .map(|i| (i as f64 * std::f64::consts::TAU / 16.0).sin())
+            .collect();
+        let as_i16: Vec<i16> = sine.iter().map(|s| (s * i16::MAX as f64) as i16).collect();
+        let as_u8: Vec<u8> = sine.iter().map(|s| (128.0 + s * 127.0) as u8).collect();
+
+        let expected: Vec<f32> = sine.iter().map(|&s| s as f32).collect();
+        let from_i16: Vec<f32> = samples_to_f32(&as_i16).collect();
+        let from_u8: Vec<f32> = samples_to_f32(&as_u8).collect();
+        for ((e, a), b) in expected.iter().zip(&from_i16).zip(&from_u8) {
+            assert!((e - a).abs() < 1e-3);
+            assert!((e - b).abs() < 2e-2);
+        }
+    }
 }
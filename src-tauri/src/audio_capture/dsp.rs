@@ -0,0 +1,427 @@
+//! Pre-processing applied to captured audio before pitch detection: a high-pass filter against
+//! rumble, a noise gate against room noise, and spectral subtraction of a noise profile learned
+//! while the singer is silent.
+
+use std::{f32::consts::PI, sync::Arc};
+
+use realfft::{ComplexToReal, RealFftPlanner, RealToComplex, num_complex::Complex};
+use serde::{Deserialize, Serialize};
+
+/// Number of samples in each FFT frame used for noise reduction.
+const NOISE_FFT_SIZE: usize = 1024;
+/// Hop between FFT frames. Frames overlap by half, which the square-root Hann windows need to
+/// reconstruct the signal exactly.
+const NOISE_HOP_SIZE: usize = NOISE_FFT_SIZE / 2;
+/// Fraction of each bin's magnitude that is always kept, so that bins aren't switched fully on
+/// and off from frame to frame ("musical noise").
+const SPECTRAL_FLOOR: f32 = 0.05;
+
+/// The pre-processing chain run on a capture session. Every stage is optional.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PreprocessingOptions {
+    /// Cutoff frequency of the high-pass filter, in Hz.
+    pub high_pass: Option<f32>,
+    pub noise_gate: Option<NoiseGateOptions>,
+    pub noise_reduction: Option<NoiseReductionOptions>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoiseGateOptions {
+    /// RMS level, linear with `1.0` being full scale, below which the gate closes.
+    pub threshold: f32,
+    /// Time for the gate to open, in milliseconds.
+    #[serde(default = "NoiseGateOptions::default_attack")]
+    pub attack: f32,
+    /// Time for the gate to close, in milliseconds.
+    #[serde(default = "NoiseGateOptions::default_release")]
+    pub release: f32,
+}
+
+impl NoiseGateOptions {
+    fn default_attack() -> f32 {
+        5.0
+    }
+
+    fn default_release() -> f32 {
+        100.0
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NoiseReductionOptions {
+    /// Length of the calibration period at the start of the session, in milliseconds. The
+    /// singer should be silent while the noise profile is learned.
+    #[serde(default = "NoiseReductionOptions::default_calibration")]
+    pub calibration: u64,
+    /// How much of the noise profile to subtract. `1.0` subtracts the average noise spectrum;
+    /// larger values remove more noise at the cost of more distortion.
+    #[serde(default = "NoiseReductionOptions::default_strength")]
+    pub strength: f32,
+}
+
+impl NoiseReductionOptions {
+    fn default_calibration() -> u64 {
+        1000
+    }
+
+    fn default_strength() -> f32 {
+        1.5
+    }
+}
+
+/// Runs the pre-processing chain on one channel of audio.
+pub struct Preprocessor {
+    high_pass: Option<HighPassFilter>,
+    noise_gate: Option<NoiseGate>,
+    noise_reduction: Option<NoiseReducer>,
+}
+
+impl Preprocessor {
+    pub fn new(options: &PreprocessingOptions, sample_rate: u32) -> Self {
+        Self {
+            high_pass: options
+                .high_pass
+                .map(|cutoff| HighPassFilter::new(cutoff, sample_rate)),
+            noise_gate: options
+                .noise_gate
+                .as_ref()
+                .map(|options| NoiseGate::new(options, sample_rate)),
+            noise_reduction: options
+                .noise_reduction
+                .as_ref()
+                .map(|options| NoiseReducer::new(options, sample_rate)),
+        }
+    }
+
+    /// Process a block of audio. Noise reduction works on whole FFT frames, so when it is
+    /// enabled the output lags the input and may be shorter or longer than it.
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        let mut output = input.to_vec();
+        if let Some(high_pass) = &mut self.high_pass {
+            high_pass.process(&mut output);
+        }
+        if let Some(noise_reduction) = &mut self.noise_reduction {
+            output = noise_reduction.process(&output);
+        }
+        // The gate comes last so that it sees the noise-reduced level.
+        if let Some(noise_gate) = &mut self.noise_gate {
+            noise_gate.process(&mut output);
+        }
+        output
+    }
+}
+
+/// Coefficient of a one-pole smoother that covers about 63% of a step in `time_ms`.
+fn smoothing_coefficient(time_ms: f32, sample_rate: u32) -> f32 {
+    let samples = time_ms / 1000.0 * sample_rate as f32;
+    if samples <= 1.0 {
+        1.0
+    } else {
+        1.0 - (-1.0 / samples).exp()
+    }
+}
+
+/// A second-order Butterworth high-pass filter.
+pub struct HighPassFilter {
+    b: [f32; 3],
+    a: [f32; 2],
+    /// Previous two inputs and outputs.
+    x: [f32; 2],
+    y: [f32; 2],
+}
+
+impl HighPassFilter {
+    pub fn new(cutoff: f32, sample_rate: u32) -> Self {
+        // From the Audio EQ Cookbook, with Q = 1/sqrt(2).
+        let cutoff = cutoff.clamp(1.0, sample_rate as f32 * 0.49);
+        let omega = 2.0 * PI * cutoff / sample_rate as f32;
+        let alpha = omega.sin() / 2.0f32.sqrt();
+        let cos = omega.cos();
+        let a0 = 1.0 + alpha;
+
+        Self {
+            b: [
+                (1.0 + cos) / 2.0 / a0,
+                -(1.0 + cos) / a0,
+                (1.0 + cos) / 2.0 / a0,
+            ],
+            a: [-2.0 * cos / a0, (1.0 - alpha) / a0],
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            let x = *sample;
+            let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+                - self.a[0] * self.y[0]
+                - self.a[1] * self.y[1];
+            self.x = [x, self.x[0]];
+            self.y = [y, self.y[0]];
+            *sample = y;
+        }
+    }
+}
+
+/// Silences the signal while its level is below a threshold.
+pub struct NoiseGate {
+    threshold: f32,
+    attack: f32,
+    release: f32,
+    /// Smoothing of the mean square level the gate decision is based on.
+    detector: f32,
+    mean_square: f32,
+    gain: f32,
+}
+
+impl NoiseGate {
+    pub fn new(options: &NoiseGateOptions, sample_rate: u32) -> Self {
+        Self {
+            threshold: options.threshold,
+            attack: smoothing_coefficient(options.attack, sample_rate),
+            release: smoothing_coefficient(options.release, sample_rate),
+            detector: smoothing_coefficient(10.0, sample_rate),
+            mean_square: 0.0,
+            gain: 0.0,
+        }
+    }
+
+    pub fn process(&mut self, samples: &mut [f32]) {
+        for sample in samples {
+            self.mean_square += (*sample * *sample - self.mean_square) * self.detector;
+            let (target, coefficient) = if self.mean_square.sqrt() >= self.threshold {
+                (1.0, self.attack)
+            } else {
+                (0.0, self.release)
+            };
+            self.gain += (target - self.gain) * coefficient;
+            *sample *= self.gain;
+        }
+    }
+}
+
+/// Spectral subtraction of a noise profile learned during a calibration period. Audio passes
+/// through unchanged (but delayed) while the profile is being learned.
+pub struct NoiseReducer {
+    forward: Arc<dyn RealToComplex<f32>>,
+    inverse: Arc<dyn ComplexToReal<f32>>,
+    /// Square-root Hann window, applied both before the FFT and after the inverse FFT.
+    window: Vec<f32>,
+    strength: f32,
+    /// Number of frames still to be used for calibration.
+    calibration_frames: usize,
+    /// Sum of the magnitude spectra seen during calibration, then their average.
+    noise_profile: Vec<f32>,
+    profile_frames: usize,
+    /// Input that hasn't been through a whole frame yet.
+    input: Vec<f32>,
+    /// Overlap-added output, of which the first `NOISE_HOP_SIZE` samples are complete.
+    output: Vec<f32>,
+    spectrum: Vec<Complex<f32>>,
+}
+
+impl NoiseReducer {
+    pub fn new(options: &NoiseReductionOptions, sample_rate: u32) -> Self {
+        let mut planner = RealFftPlanner::<f32>::new();
+        let forward = planner.plan_fft_forward(NOISE_FFT_SIZE);
+        let inverse = planner.plan_fft_inverse(NOISE_FFT_SIZE);
+        let window = (0..NOISE_FFT_SIZE)
+            .map(|i| (PI * i as f32 / NOISE_FFT_SIZE as f32).sin())
+            .collect();
+        let calibration_samples = options.calibration * sample_rate as u64 / 1000;
+        let spectrum = forward.make_output_vec();
+
+        Self {
+            forward,
+            inverse,
+            window,
+            strength: options.strength,
+            calibration_frames: (calibration_samples as usize / NOISE_HOP_SIZE).max(1),
+            noise_profile: vec![0.0; spectrum.len()],
+            profile_frames: 0,
+            input: vec![0.0; NOISE_HOP_SIZE],
+            output: vec![0.0; NOISE_FFT_SIZE],
+            spectrum,
+        }
+    }
+
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        self.input.extend_from_slice(input);
+
+        let mut output = vec![];
+        let mut frame = vec![0.0; NOISE_FFT_SIZE];
+        while self.input.len() >= NOISE_FFT_SIZE {
+            for ((frame, input), window) in frame.iter_mut().zip(&self.input).zip(&self.window) {
+                *frame = input * window;
+            }
+            self.input.drain(..NOISE_HOP_SIZE);
+
+            // Lengths always match the plan, so the FFTs can't fail.
+            let _ = self.forward.process(&mut frame, &mut self.spectrum);
+            self.subtract_noise();
+            let _ = self.inverse.process(&mut self.spectrum, &mut frame);
+
+            let scale = 1.0 / NOISE_FFT_SIZE as f32;
+            for ((output, frame), window) in self.output.iter_mut().zip(&frame).zip(&self.window) {
+                *output += frame * window * scale;
+            }
+            output.extend(self.output.drain(..NOISE_HOP_SIZE));
+            self.output.resize(NOISE_FFT_SIZE, 0.0);
+        }
+        output
+    }
+
+    /// Learn from the spectrum while calibrating, otherwise subtract the learned profile from it.
+    fn subtract_noise(&mut self) {
+        if self.calibration_frames > 0 {
+            for (profile, bin) in self.noise_profile.iter_mut().zip(&self.spectrum) {
+                *profile += bin.norm();
+            }
+            self.profile_frames += 1;
+            self.calibration_frames -= 1;
+            if self.calibration_frames == 0 {
+                for profile in &mut self.noise_profile {
+                    *profile *= self.strength / self.profile_frames as f32;
+                }
+            }
+            return;
+        }
+
+        for (bin, noise) in self.spectrum.iter_mut().zip(&self.noise_profile) {
+            let magnitude = bin.norm();
+            if magnitude > 0.0 {
+                let reduced = (magnitude - noise).max(magnitude * SPECTRAL_FLOOR);
+                *bin *= reduced / magnitude;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    const SAMPLE_RATE: u32 = 16_000;
+
+    fn sine(frequency: f32, amplitude: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| amplitude * (TAU * frequency * i as f32 / SAMPLE_RATE as f32).sin())
+            .collect()
+    }
+
+    /// Deterministic white noise, uniform in `-amplitude..amplitude`.
+    fn noise(amplitude: f32, len: usize, seed: u32) -> Vec<f32> {
+        let mut state = seed;
+        (0..len)
+            .map(|_| {
+                state = state.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+                amplitude * ((state >> 8) as f32 / (1 << 23) as f32 - 1.0)
+            })
+            .collect()
+    }
+
+    fn mix(a: &[f32], b: &[f32]) -> Vec<f32> {
+        a.iter().zip(b).map(|(a, b)| a + b).collect()
+    }
+
+    fn rms(samples: &[f32]) -> f32 {
+        (samples.iter().map(|s| s * s).sum::<f32>() / samples.len() as f32).sqrt()
+    }
+
+    /// Magnitude of `signal` at `frequency`, normalized so a full-scale sine gives 0.5.
+    fn magnitude_at(signal: &[f32], frequency: f32) -> f32 {
+        let (re, im) = signal
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (i, s)| {
+                let phase = TAU * frequency * i as f32 / SAMPLE_RATE as f32;
+                (re + s * phase.cos(), im - s * phase.sin())
+            });
+        (re * re + im * im).sqrt() / signal.len() as f32
+    }
+
+    #[test]
+    fn high_pass_removes_rumble_and_keeps_the_voice() {
+        let input = mix(&sine(30.0, 0.5, 16_000), &sine(440.0, 0.2, 16_000));
+        let mut output = input.clone();
+        HighPassFilter::new(150.0, SAMPLE_RATE).process(&mut output);
+
+        // Skip the filter settling in.
+        let steady = &output[4_000..];
+        assert!(magnitude_at(steady, 30.0) < 0.25 * 0.1);
+        assert!((magnitude_at(steady, 440.0) - 0.1).abs() < 0.01);
+    }
+
+    #[test]
+    fn noise_gate_silences_noise_between_notes() {
+        let options = NoiseGateOptions {
+            threshold: 0.05,
+            attack: 5.0,
+            release: 50.0,
+        };
+        let room_noise = noise(0.02, 8_000, 1);
+        let note = mix(&sine(440.0, 0.3, 8_000), &noise(0.02, 8_000, 2));
+        let input = [room_noise.clone(), note, room_noise].concat();
+
+        let mut output = input.clone();
+        NoiseGate::new(&options, SAMPLE_RATE).process(&mut output);
+
+        // Noise before the note never opens the gate.
+        assert!(rms(&output[..8_000]) < 1e-3);
+        // The note passes once the gate has opened...
+        let note = &output[8_400..16_000];
+        assert!((rms(note) - rms(&input[8_400..16_000])).abs() < 0.01);
+        // ...and the noise after it is gone once the gate has closed again.
+        assert!(rms(&output[20_000..]) < 1e-3);
+    }
+
+    #[test]
+    fn noise_reduction_removes_the_learned_noise_profile() {
+        let options = NoiseReductionOptions {
+            calibration: 1000,
+            strength: 1.5,
+        };
+        let mut reducer = NoiseReducer::new(&options, SAMPLE_RATE);
+
+        // One second of room noise to calibrate on, then noise alone, then a note in the noise.
+        let calibration = noise(0.05, 16_000, 3);
+        let noise_only = noise(0.05, 16_000, 4);
+        let note = sine(440.0, 0.2, 16_000);
+        let noisy_note = mix(&note, &noise(0.05, 16_000, 5));
+        let input = [calibration, noise_only.clone(), noisy_note].concat();
+
+        let mut output = vec![];
+        for block in input.chunks(300) {
+            output.extend(reducer.process(block));
+        }
+        // The output lags the input by one hop.
+        let output = &output[NOISE_HOP_SIZE..];
+
+        let noise_after = rms(&output[17_000..31_000]);
+        assert!(
+            noise_after < 0.25 * rms(&noise_only),
+            "noise went from {} to {}",
+            rms(&noise_only),
+            noise_after
+        );
+        let note_after = magnitude_at(&output[33_000..47_000], 440.0);
+        assert!(note_after > 0.08, "note magnitude {}", note_after);
+    }
+
+    #[test]
+    fn noise_reduction_passes_audio_through_while_calibrating() {
+        let options = NoiseReductionOptions {
+            calibration: 2000,
+            strength: 1.5,
+        };
+        let mut reducer = NoiseReducer::new(&options, SAMPLE_RATE);
+        let input = sine(440.0, 0.5, 16_000);
+        let output = reducer.process(&input);
+
+        for (output, input) in output[NOISE_HOP_SIZE..].iter().zip(&input) {
+            assert!((output - input).abs() < 1e-4);
+        }
+    }
+}
@@ -0,0 +1,97 @@
+//! JACK support for audio capture. When the `jack` feature is enabled, capture goes through
+//! cpal's JACK host (which also covers PipeWire's JACK implementation) and can be routed from
+//! any JACK output port. Otherwise everything falls back to the platform's default host.
+
+/// The cpal host used for capture: JACK if the `jack` feature is enabled and a JACK server is
+/// running, otherwise the platform's default host.
+#[cfg(all(feature = "jack", target_os = "linux"))]
+pub fn audio_host() -> cpal::Host {
+    use cpal::traits::HostTrait;
+
+    match cpal::host_from_id(cpal::HostId::Jack) {
+        // cpal creates the JACK host even when no server is running; it just has no devices.
+        Ok(host) if host.default_input_device().is_some() => host,
+        Ok(_) => {
+            eprintln!("No JACK server is running. Falling back to the default audio host.");
+            cpal::default_host()
+        }
+        Err(err) => {
+            eprintln!(
+                "JACK host is unavailable ({}). Falling back to the default audio host.",
+                err
+            );
+            cpal::default_host()
+        }
+    }
+}
+
+#[cfg(not(all(feature = "jack", target_os = "linux")))]
+pub fn audio_host() -> cpal::Host {
+    cpal::default_host()
+}
+
+/// List the JACK ports that audio can be captured from, i.e. every audio output port,
+/// including the sound card's capture ports.
+#[cfg(all(feature = "jack", target_os = "linux"))]
+pub fn list_ports() -> Result<Vec<String>, String> {
+    use jack::PortSpec;
+
+    let client = open_client()?;
+    Ok(client.ports(
+        None,
+        Some(jack::AudioIn::default().jack_port_type()),
+        jack::PortFlags::IS_OUTPUT,
+    ))
+}
+
+#[cfg(not(all(feature = "jack", target_os = "linux")))]
+pub fn list_ports() -> Result<Vec<String>, String> {
+    Err("JACK support is not enabled in this build".to_string())
+}
+
+/// Route `port` into every input port of the JACK client `client_name`, replacing any existing
+/// connections (cpal connects its client to the system capture ports by default).
+///
+/// Does nothing if `client_name` is not a JACK client, e.g. because capture fell back to the
+/// default host.
+#[cfg(all(feature = "jack", target_os = "linux"))]
+pub fn connect_port(client_name: &str, port: &str) -> Result<(), String> {
+    let client = open_client()?;
+    if client.port_by_name(port).is_none() {
+        return Err(format!("JACK port not found: {}", port));
+    }
+
+    let prefix = format!("{}:", client_name);
+    let input_ports: Vec<String> = client
+        .ports(None, None, jack::PortFlags::IS_INPUT)
+        .into_iter()
+        .filter(|name| name.starts_with(&prefix))
+        .collect();
+    for input_port in &input_ports {
+        if let Some(unowned) = client.port_by_name(input_port) {
+            for source in unowned.get_connections() {
+                client
+                    .disconnect_ports_by_name(&source, input_port)
+                    .map_err(|err| err.to_string())?;
+            }
+        }
+        client
+            .connect_ports_by_name(port, input_port)
+            .map_err(|err| err.to_string())?;
+    }
+
+    Ok(())
+}
+
+#[cfg(not(all(feature = "jack", target_os = "linux")))]
+pub fn connect_port(_client_name: &str, _port: &str) -> Result<(), String> {
+    Ok(())
+}
+
+/// Open a short-lived client used only to query and rewire the JACK graph.
+#[cfg(all(feature = "jack", target_os = "linux"))]
+fn open_client() -> Result<jack::Client, String> {
+    jack::Client::new("tauri_pitch_patchbay", jack::ClientOptions::NO_START_SERVER)
+        .map(|(client, _status)| client)
+        .map_err(|err| format!("Could not connect to the JACK server: {}", err))
+}
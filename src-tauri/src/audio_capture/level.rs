@@ -0,0 +1,148 @@
+//! Input level metering, so the UI can show whether the microphone is picking anything up and
+//! warn about clipping.
+
+use serde::Serialize;
+
+/// Samples at or above this magnitude are counted as clipped. Converted integer samples never
+/// quite reach `1.0` on the positive side, so the threshold is just below it.
+const CLIP_THRESHOLD: f32 = 0.999;
+/// RMS below which the input is reported as silent (about -60 dBFS).
+const SILENCE_THRESHOLD: f32 = 0.001;
+
+/// The level of the input over one metering interval, emitted as an `audio:level` event.
+/// Levels are linear, with `1.0` being full scale, and cover every channel of the device.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub struct InputLevel {
+    pub rms: f32,
+    pub peak: f32,
+    /// Number of samples that were at or above full scale.
+    pub clipped: usize,
+    /// Whether the RMS was low enough that the input is probably not connected or muted.
+    pub silent: bool,
+    /// Start of the interval, in seconds since the capture session started.
+    pub timestamp: f64,
+}
+
+/// Accumulates interleaved audio and reports its level once per interval.
+pub struct LevelMeter {
+    sample_rate: u32,
+    channels: usize,
+    /// Number of frames in each metering interval.
+    interval: usize,
+    /// Frames measured so far, including the current interval.
+    position: u64,
+    frames: usize,
+    sum_of_squares: f64,
+    peak: f32,
+    clipped: usize,
+}
+
+impl LevelMeter {
+    pub fn new(sample_rate: u32, channels: usize, interval_ms: u64) -> Self {
+        Self {
+            sample_rate,
+            channels: channels.max(1),
+            interval: (sample_rate as u64 * interval_ms / 1000).max(1) as usize,
+            position: 0,
+            frames: 0,
+            sum_of_squares: 0.0,
+            peak: 0.0,
+            clipped: 0,
+        }
+    }
+
+    /// Measure a block of interleaved audio, returning the level of every interval it completes.
+    pub fn process(&mut self, interleaved: &[f32]) -> Vec<InputLevel> {
+        let mut levels = vec![];
+        for frame in interleaved.chunks_exact(self.channels) {
+            for &sample in frame {
+                let magnitude = sample.abs();
+                self.sum_of_squares += (sample as f64) * (sample as f64);
+                self.peak = self.peak.max(magnitude);
+                if magnitude >= CLIP_THRESHOLD {
+                    self.clipped += 1;
+                }
+            }
+            self.frames += 1;
+            self.position += 1;
+
+            if self.frames == self.interval {
+                levels.push(self.finish_interval());
+            }
+        }
+        levels
+    }
+
+    fn finish_interval(&mut self) -> InputLevel {
+        let rms = (self.sum_of_squares / (self.frames * self.channels) as f64).sqrt() as f32;
+        let level = InputLevel {
+            rms,
+            peak: self.peak,
+            clipped: self.clipped,
+            silent: rms < SILENCE_THRESHOLD,
+            timestamp: (self.position - self.frames as u64) as f64 / self.sample_rate as f64,
+        };
+
+        self.frames = 0;
+        self.sum_of_squares = 0.0;
+        self.peak = 0.0;
+        self.clipped = 0;
+        level
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sine(amplitude: f32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| amplitude * (i as f32 * std::f32::consts::TAU / 100.0).sin())
+            .collect()
+    }
+
+    #[test]
+    fn sine_level_is_measured_per_interval() {
+        let mut meter = LevelMeter::new(10_000, 1, 100);
+        // 2.5 intervals of a half-scale sine.
+        let levels = meter.process(&sine(0.5, 2_500));
+
+        assert_eq!(levels.len(), 2);
+        for level in &levels {
+            assert!((level.rms - 0.5 / 2f32.sqrt()).abs() < 1e-3);
+            assert!((level.peak - 0.5).abs() < 1e-3);
+            assert_eq!(level.clipped, 0);
+            assert!(!level.silent);
+        }
+        assert_eq!(levels[1].timestamp, 0.1);
+
+        // The half interval left over is completed by the next block.
+        let levels = meter.process(&sine(0.5, 500));
+        assert_eq!(levels.len(), 1);
+        assert_eq!(levels[0].timestamp, 0.2);
+    }
+
+    #[test]
+    fn clipping_is_counted_across_channels() {
+        let mut meter = LevelMeter::new(1_000, 2, 100);
+        // A clipped left channel and a quiet right channel.
+        let interleaved: Vec<f32> = (0..100)
+            .flat_map(|i| [if i % 2 == 0 { 1.0 } else { -1.0 }, 0.01])
+            .collect();
+        let levels = meter.process(&interleaved);
+
+        assert_eq!(levels.len(), 1);
+        assert_eq!(levels[0].clipped, 100);
+        assert_eq!(levels[0].peak, 1.0);
+        assert!((levels[0].rms - (1.0001f32 / 2.0).sqrt()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn quiet_input_is_reported_as_silent() {
+        let mut meter = LevelMeter::new(1_000, 1, 100);
+        let levels = meter.process(&sine(0.0005, 100));
+        assert!(levels[0].silent);
+        let levels = meter.process(&sine(0.05, 100));
+        assert!(!levels[0].silent);
+    }
+}
@@ -0,0 +1,113 @@
+//! Native pitch detection on captured audio, so the desktop app doesn't need the WASM worker.
+
+use pitch_detection::detector::PitchDetector;
+use pitch_detection::detector::autocorrelation::AutocorrelationDetector;
+use pitch_detection::detector::mcleod::McLeodDetector;
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PitchAlgorithm {
+    Autocorrelation,
+    McLeod,
+    /// Only implemented in `pitch-detection-wasm`, so `PitchTracker::new` rejects it.
+    Yin,
+    /// Only implemented in `pitch-detection-wasm`, so `PitchTracker::new` rejects it.
+    Pyin,
+}
+
+/// Settings for the pitch detector run on a capture session. These mirror the arguments of
+/// the detectors in `pitch-detection-wasm`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PitchDetectionOptions {
+    pub algorithm: PitchAlgorithm,
+    /// The minimum power for the detector to guess a pitch for.
+    pub power_threshold: f32,
+    /// The minimum clarity for a detected pitch to be reported.
+    pub clarity_threshold: f32,
+    /// Zero padding added to each window. Defaults to half the window size.
+    pub padding: Option<usize>,
+}
+
+/// A detected pitch, emitted as an `audio:pitch` event. As in `pitch-detection-wasm`, a
+/// `frequency` of `-1.0` means no pitch was found. `timestamp` is the position of the start of
+/// the analysed window, in seconds since the capture session started.
+#[derive(Debug, Clone, Serialize)]
+pub struct PitchEvent {
+    pub frequency: f32,
+    pub clarity: f32,
+    pub timestamp: f64,
+}
+
+/// Runs a pitch detector over consecutive windows of a single channel.
+pub struct PitchTracker {
+    detector: Box<dyn PitchDetector<f32>>,
+    options: PitchDetectionOptions,
+    window_size: usize,
+    sample_rate: u32,
+    /// Number of samples analysed so far, used to timestamp the results.
+    position: u64,
+}
+
+impl PitchTracker {
+    /// Fails if the algorithm can't be run natively.
+    pub fn new(
+        options: PitchDetectionOptions,
+        window_size: usize,
+        sample_rate: u32,
+    ) -> Result<Self, String> {
+        let padding = options.padding.unwrap_or(window_size / 2);
+        let detector: Box<dyn PitchDetector<f32>> = match options.algorithm {
+            PitchAlgorithm::Autocorrelation => {
+                Box::new(AutocorrelationDetector::<f32>::new(window_size, padding))
+            }
+            PitchAlgorithm::McLeod => Box::new(McLeodDetector::<f32>::new(window_size, padding)),
+            PitchAlgorithm::Yin | PitchAlgorithm::Pyin => {
+                return Err(format!(
+                    "The {:?} pitch detector is only available in the browser. Use \
+                     autocorrelation or McLeod for native pitch detection.",
+                    options.algorithm
+                ));
+            }
+        };
+
+        Ok(Self {
+            detector,
+            options,
+            window_size,
+            sample_rate,
+            position: 0,
+        })
+    }
+
+    /// Detect the pitch of the next window of audio, which must be `window_size` samples long.
+    pub fn detect(&mut self, window: &[f32]) -> PitchEvent {
+        let timestamp = self.position as f64 / self.sample_rate as f64;
+        self.position += window.len() as u64;
+        if window.len() != self.window_size {
+            return PitchEvent {
+                frequency: -1.0,
+                clarity: 0.0,
+                timestamp,
+            };
+        }
+
+        match self.detector.get_pitch(
+            window,
+            self.sample_rate as usize,
+            self.options.power_threshold,
+            self.options.clarity_threshold,
+        ) {
+            Some(pitch) => PitchEvent {
+                frequency: pitch.frequency,
+                clarity: pitch.clarity,
+                timestamp,
+            },
+            None => PitchEvent {
+                frequency: -1.0,
+                clarity: 0.0,
+                timestamp,
+            },
+        }
+    }
+}
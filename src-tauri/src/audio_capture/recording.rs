@@ -0,0 +1,182 @@
+//! Recording captured audio to WAV files so singers can listen back to their performances.
+//!
+//! Recordings are stored in the `recordings` directory of the app data dir and are named
+//! `<song key>.<unix timestamp>.wav`, or `recording.<unix timestamp>.wav` when no song was playing.
+
+use std::{
+    fs::File,
+    io::BufWriter,
+    path::{Path, PathBuf},
+};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+use serde::{Deserialize, Serialize};
+
+/// Name used in place of a song key for recordings made while no song was playing.
+const NO_SONG_KEY: &str = "recording";
+
+/// A past recording found in the recordings directory.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordingInfo {
+    pub file_name: String,
+    pub path: PathBuf,
+    /// Key of the song that was playing, if any.
+    pub song_key: Option<String>,
+    /// When the recording was started, in seconds since the UNIX epoch.
+    pub timestamp: u64,
+    /// Length of the recording in seconds.
+    pub duration: f64,
+}
+
+/// Writes interleaved audio to a WAV file as it is captured.
+pub struct Recorder {
+    writer: WavWriter<BufWriter<File>>,
+    file_name: String,
+}
+
+impl Recorder {
+    pub fn create(
+        dir: &Path,
+        song_key: Option<&str>,
+        timestamp: u64,
+        channels: u16,
+        sample_rate: u32,
+    ) -> Result<Self, String> {
+        std::fs::create_dir_all(dir).map_err(|err| err.to_string())?;
+        let file_name = recording_file_name(song_key, timestamp);
+        let spec = WavSpec {
+            channels,
+            sample_rate,
+            bits_per_sample: 32,
+            sample_format: SampleFormat::Float,
+        };
+        let writer =
+            WavWriter::create(dir.join(&file_name), spec).map_err(|err| err.to_string())?;
+
+        Ok(Self { writer, file_name })
+    }
+
+    pub fn file_name(&self) -> &str {
+        &self.file_name
+    }
+
+    /// Append a block of audio, given as one buffer per channel.
+    pub fn write(&mut self, channels: &[Vec<f32>]) -> Result<(), String> {
+        let len = channels.iter().map(Vec::len).min().unwrap_or(0);
+        for i in 0..len {
+            for channel in channels {
+                self.writer
+                    .write_sample(channel[i])
+                    .map_err(|err| err.to_string())?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Finish writing the WAV header and close the file. Returns the recording's file name.
+    pub fn finish(self) -> Result<String, String> {
+        self.writer.finalize().map_err(|err| err.to_string())?;
+        Ok(self.file_name)
+    }
+}
+
+fn recording_file_name(song_key: Option<&str>, timestamp: u64) -> String {
+    // Song keys are YouTube ids, but make sure nothing can escape the recordings directory.
+    let song_key = song_key
+        .map(|key| key.replace(['.', '/', '\\'], "_"))
+        .filter(|key| !key.is_empty())
+        .unwrap_or_else(|| NO_SONG_KEY.to_string());
+    format!("{}.{}.wav", song_key, timestamp)
+}
+
+/// Split a recording's file name into its song key and timestamp.
+fn parse_recording_file_name(file_name: &str) -> Option<(Option<String>, u64)> {
+    let (song_key, timestamp) = file_name.strip_suffix(".wav")?.split_once('.')?;
+    let timestamp = timestamp.parse().ok()?;
+    let song_key = (song_key != NO_SONG_KEY).then(|| song_key.to_string());
+    Some((song_key, timestamp))
+}
+
+/// List the recordings in `dir`, newest first.
+pub fn list_recordings(dir: &Path) -> Result<Vec<RecordingInfo>, String> {
+    if !dir.exists() {
+        return Ok(vec![]);
+    }
+
+    let mut recordings = vec![];
+    for entry in std::fs::read_dir(dir).map_err(|err| err.to_string())? {
+        let entry = entry.map_err(|err| err.to_string())?;
+        let Some(file_name) = entry.file_name().to_str().map(|name| name.to_string()) else {
+            continue;
+        };
+        let Some((song_key, timestamp)) = parse_recording_file_name(&file_name) else {
+            continue;
+        };
+        // A recording that is still being written (or was cut short by a crash) has no valid
+        // header yet, so report it with a length of zero.
+        let duration = hound::WavReader::open(entry.path())
+            .map(|reader| reader.duration() as f64 / reader.spec().sample_rate as f64)
+            .unwrap_or(0.0);
+        recordings.push(RecordingInfo {
+            file_name,
+            path: entry.path(),
+            song_key,
+            timestamp,
+            duration,
+        });
+    }
+    recordings.sort_by_key(|recording| std::cmp::Reverse(recording.timestamp));
+
+    Ok(recordings)
+}
+
+/// Delete the recording called `file_name` from `dir`.
+pub fn delete_recording(dir: &Path, file_name: &str) -> Result<(), String> {
+    if parse_recording_file_name(file_name).is_none() || file_name.contains(['/', '\\']) {
+        return Err(format!("Not a recording: {}", file_name));
+    }
+    std::fs::remove_file(dir.join(file_name)).map_err(|err| err.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn recordings_round_trip_through_the_recordings_dir() {
+        let dir =
+            std::env::temp_dir().join(format!("tauri-pitch-recordings-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+
+        let mut recorder =
+            Recorder::create(&dir, Some("dQw4w9WgXcQ"), 1_700_000_000, 2, 8_000).unwrap();
+        recorder
+            .write(&[vec![0.5; 4_000], vec![-0.5; 4_000]])
+            .unwrap();
+        assert_eq!(recorder.finish().unwrap(), "dQw4w9WgXcQ.1700000000.wav");
+        Recorder::create(&dir, None, 1_700_000_100, 1, 8_000)
+            .unwrap()
+            .finish()
+            .unwrap();
+
+        let recordings = list_recordings(&dir).unwrap();
+        assert_eq!(recordings.len(), 2);
+        assert_eq!(recordings[0].song_key, None);
+        assert_eq!(recordings[1].song_key.as_deref(), Some("dQw4w9WgXcQ"));
+        assert_eq!(recordings[1].duration, 0.5);
+
+        let mut reader = hound::WavReader::open(&recordings[1].path).unwrap();
+        let samples: Vec<f32> = reader
+            .samples::<f32>()
+            .take(2)
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(samples, vec![0.5, -0.5]);
+
+        assert!(delete_recording(&dir, "../dQw4w9WgXcQ.1700000000.wav").is_err());
+        delete_recording(&dir, "dQw4w9WgXcQ.1700000000.wav").unwrap();
+        assert_eq!(list_recordings(&dir).unwrap().len(), 1);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,172 @@
+//! Resampling of captured audio to a fixed analysis sample rate, so that a window of N samples
+//! covers the same duration no matter which rate the input device runs at.
+
+use rubato::{FftFixedIn, Resampler as _};
+
+/// Number of input frames the FFT resampler processes at a time.
+const CHUNK_SIZE: usize = 1024;
+
+/// A streaming resampler for de-interleaved audio. Input can be fed in blocks of any size;
+/// output is produced one resampler chunk at a time.
+pub struct Resampler {
+    inner: FftFixedIn<f32>,
+    /// Input that doesn't fill a whole chunk yet, one buffer per channel.
+    pending: Vec<Vec<f32>>,
+    /// Number of output samples still to be dropped to compensate for the resampler's delay.
+    delay_remaining: usize,
+}
+
+impl Resampler {
+    pub fn new(from_rate: u32, to_rate: u32, num_channels: usize) -> Result<Self, String> {
+        let inner = FftFixedIn::<f32>::new(
+            from_rate as usize,
+            to_rate as usize,
+            CHUNK_SIZE,
+            2,
+            num_channels,
+        )
+        .map_err(|err| err.to_string())?;
+        let delay_remaining = inner.output_delay();
+
+        Ok(Self {
+            inner,
+            pending: vec![Vec::new(); num_channels],
+            delay_remaining,
+        })
+    }
+
+    /// Feed `input` (one buffer per channel) to the resampler and return the resampled audio
+    /// that is ready so far.
+    pub fn process(&mut self, input: &[Vec<f32>]) -> Result<Vec<Vec<f32>>, String> {
+        for (pending, channel) in self.pending.iter_mut().zip(input) {
+            pending.extend_from_slice(channel);
+        }
+
+        let mut output = vec![Vec::new(); self.pending.len()];
+        let chunk_size = self.inner.input_frames_next();
+        while self
+            .pending
+            .first()
+            .is_some_and(|pending| pending.len() >= chunk_size)
+        {
+            let chunk: Vec<&[f32]> = self
+                .pending
+                .iter()
+                .map(|pending| &pending[..chunk_size])
+                .collect();
+            let resampled = self
+                .inner
+                .process(&chunk, None)
+                .map_err(|err| err.to_string())?;
+            for (output, resampled) in output.iter_mut().zip(resampled) {
+                output.extend(resampled);
+            }
+            for pending in &mut self.pending {
+                pending.drain(..chunk_size);
+            }
+        }
+
+        // The first samples out of the resampler are its filter warming up, not our audio.
+        let skip = self.delay_remaining.min(output.first().map_or(0, Vec::len));
+        self.delay_remaining -= skip;
+        for output in &mut output {
+            output.drain(..skip);
+        }
+
+        Ok(output)
+    }
+}
+
+/// Resample a complete recording (one buffer per channel) from `from_rate` to `to_rate`.
+pub fn resample(input: &[Vec<f32>], from_rate: u32, to_rate: u32) -> Result<Vec<Vec<f32>>, String> {
+    if from_rate == to_rate || input.is_empty() {
+        return Ok(input.to_vec());
+    }
+
+    let expected_len = (input[0].len() as u64 * to_rate as u64).div_ceil(from_rate as u64) as usize;
+    let mut resampler = Resampler::new(from_rate, to_rate, input.len())?;
+    let mut output = resampler.process(input)?;
+
+    // Push silence through until every input sample has made it out of the resampler.
+    let silence = vec![vec![0.0; CHUNK_SIZE]; input.len()];
+    while output[0].len() < expected_len {
+        for (output, resampled) in output.iter_mut().zip(resampler.process(&silence)?) {
+            output.extend(resampled);
+        }
+    }
+    for output in &mut output {
+        output.truncate(expected_len);
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f32::consts::TAU;
+
+    fn sine(frequency: f32, sample_rate: u32, len: usize) -> Vec<f32> {
+        (0..len)
+            .map(|i| (TAU * frequency * i as f32 / sample_rate as f32).sin())
+            .collect()
+    }
+
+    /// Magnitude of the DFT of `signal` at `frequency`, normalized so a full-scale sine gives 0.5.
+    fn magnitude_at(signal: &[f32], frequency: f32, sample_rate: u32) -> f32 {
+        let (re, im) = signal
+            .iter()
+            .enumerate()
+            .fold((0.0, 0.0), |(re, im), (i, s)| {
+                let phase = TAU * frequency * i as f32 / sample_rate as f32;
+                (re + s * phase.cos(), im - s * phase.sin())
+            });
+        (re * re + im * im).sqrt() / signal.len() as f32
+    }
+
+    #[test]
+    fn resampled_sine_keeps_its_frequency() {
+        for (from_rate, to_rate) in [(48_000, 16_000), (44_100, 22_050), (96_000, 16_000)] {
+            let input = sine(440.0, from_rate, from_rate as usize);
+            let output = resample(&[input], from_rate, to_rate).unwrap();
+
+            assert_eq!(output[0].len(), to_rate as usize);
+            // Ignore the edges, where the resampler's filter sees the implicit silence.
+            let steady = &output[0][1000..to_rate as usize - 1000];
+            let at_440 = magnitude_at(steady, 440.0, to_rate);
+            let at_550 = magnitude_at(steady, 550.0, to_rate);
+            assert!(
+                at_440 > 0.45,
+                "{from_rate} -> {to_rate}: magnitude {at_440}"
+            );
+            assert!(at_550 < 0.01, "{from_rate} -> {to_rate}: leakage {at_550}");
+        }
+    }
+
+    #[test]
+    fn frequencies_above_the_new_nyquist_are_removed() {
+        let input = sine(7_000.0, 48_000, 48_000);
+        let output = resample(&[input], 48_000, 8_000).unwrap();
+
+        // 7 kHz would alias to 1 kHz at 8 kHz if it weren't filtered out.
+        let steady = &output[0][500..7_500];
+        assert!(magnitude_at(steady, 1_000.0, 8_000) < 0.01);
+    }
+
+    #[test]
+    fn streaming_matches_one_shot() {
+        let input = sine(300.0, 48_000, 48_000);
+        let one_shot = resample(std::slice::from_ref(&input), 48_000, 16_000).unwrap();
+
+        let mut resampler = Resampler::new(48_000, 16_000, 1).unwrap();
+        let mut streamed = Vec::new();
+        for block in input.chunks(700) {
+            streamed.extend(resampler.process(&[block.to_vec()]).unwrap().remove(0));
+        }
+
+        assert!(streamed.len() > 15_000);
+        for (a, b) in streamed.iter().zip(&one_shot[0]) {
+            assert!((a - b).abs() < 1e-4);
+        }
+    }
+}
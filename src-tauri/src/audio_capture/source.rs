@@ -0,0 +1,297 @@
+//! Sources of audio for capture. [`CpalSource`] captures from a real input device, while
+//! [`SyntheticSource`] plays back generated signals or WAV files in real time, so that the
+//! capture path can be exercised without a sound card.
+
+use std::{
+    f64::consts::TAU,
+    path::Path,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
+
+use cpal::{
+    Stream,
+    traits::{DeviceTrait, StreamTrait},
+};
+
+use super::{RING_BUFFER_SECONDS, build_f32_input_stream};
+
+/// A running source of interleaved `f32` audio in the range `-1.0..=1.0`.
+pub trait AudioSource {
+    fn sample_rate(&self) -> u32;
+
+    /// Number of interleaved channels.
+    fn channels(&self) -> usize;
+
+    /// Append the audio produced since the last call to `buffer`. Only whole frames are
+    /// appended. Returns the number of samples appended.
+    fn read(&mut self, buffer: &mut Vec<f32>) -> Result<usize, String>;
+}
+
+/// Captures from a cpal input device. The cpal callback writes into a lock-free ring buffer,
+/// which is drained by [`AudioSource::read`].
+pub struct CpalSource {
+    // Capture stops when the stream is dropped.
+    _stream: Stream,
+    consumer: rtrb::Consumer<f32>,
+    sample_rate: u32,
+    channels: usize,
+    /// Set by the stream's error callback when the device goes away.
+    device_lost: Arc<AtomicBool>,
+}
+
+impl CpalSource {
+    /// Start capturing from `device` with its default input configuration.
+    pub fn open(device: &cpal::Device) -> Result<Self, String> {
+        let config = device
+            .default_input_config()
+            .map_err(|err| err.to_string())?;
+
+        let sample_rate = config.sample_rate().0;
+        let channels = config.channels() as usize;
+        let (mut producer, consumer) =
+            rtrb::RingBuffer::<f32>::new(sample_rate as usize * channels * RING_BUFFER_SECONDS);
+
+        let device_lost = Arc::new(AtomicBool::new(false));
+        let err_fn = {
+            let device_lost = device_lost.clone();
+            move |err: cpal::StreamError| {
+                eprintln!("an error occurred on stream: {}", err);
+                if let cpal::StreamError::DeviceNotAvailable = err {
+                    device_lost.store(true, Ordering::SeqCst);
+                }
+            }
+        };
+
+        let stream = build_f32_input_stream(
+            device,
+            &config,
+            move |data| {
+                // Never block the audio thread. If the reader has fallen behind, the samples
+                // that don't fit are dropped.
+                let n = data.len().min(producer.slots());
+                if let Ok(chunk) = producer.write_chunk_uninit(n) {
+                    chunk.fill_from_iter(data.iter().copied());
+                }
+            },
+            err_fn,
+        )?;
+        stream.play().map_err(|err| err.to_string())?;
+
+        Ok(Self {
+            _stream: stream,
+            consumer,
+            sample_rate,
+            channels,
+            device_lost,
+        })
+    }
+}
+
+impl AudioSource for CpalSource {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn read(&mut self, buffer: &mut Vec<f32>) -> Result<usize, String> {
+        if self.device_lost.load(Ordering::SeqCst) {
+            return Err("The input device is no longer available".to_string());
+        }
+        let available = self.consumer.slots() / self.channels * self.channels;
+        let chunk = self
+            .consumer
+            .read_chunk(available)
+            .map_err(|err| err.to_string())?;
+        buffer.extend(chunk);
+        Ok(available)
+    }
+}
+
+/// Plays back a fixed buffer of audio at the rate a real device would produce it, starting
+/// when the source is created. Once the buffer has been played, the source produces nothing.
+pub struct SyntheticSource {
+    /// Interleaved samples.
+    samples: Vec<f32>,
+    sample_rate: u32,
+    channels: usize,
+    started: Instant,
+    /// Number of frames handed out so far.
+    position: usize,
+}
+
+impl SyntheticSource {
+    /// Play back interleaved `samples` with `channels` channels.
+    pub fn new(samples: Vec<f32>, sample_rate: u32, channels: usize) -> Self {
+        Self {
+            samples,
+            sample_rate,
+            channels: channels.max(1),
+            started: Instant::now(),
+            position: 0,
+        }
+    }
+
+    /// A sine wave with the same signal on every channel.
+    pub fn sine(
+        frequency: f64,
+        amplitude: f32,
+        duration: Duration,
+        sample_rate: u32,
+        channels: usize,
+    ) -> Self {
+        Self::sweep(
+            frequency,
+            frequency,
+            amplitude,
+            duration,
+            sample_rate,
+            channels,
+        )
+    }
+
+    /// A sine sweep whose frequency rises (or falls) exponentially from `from` to `to` over
+    /// `duration`, with the same signal on every channel.
+    pub fn sweep(
+        from: f64,
+        to: f64,
+        amplitude: f32,
+        duration: Duration,
+        sample_rate: u32,
+        channels: usize,
+    ) -> Self {
+        let len = (duration.as_secs_f64() * sample_rate as f64).round() as usize;
+        let mut phase = 0.0f64;
+        let mut samples = Vec::with_capacity(len * channels);
+        for i in 0..len {
+            let frequency = from * (to / from).powf(i as f64 / len as f64);
+            let sample = amplitude * phase.sin() as f32;
+            samples.extend(std::iter::repeat_n(sample, channels));
+            phase = (phase + TAU * frequency / sample_rate as f64) % TAU;
+        }
+
+        Self::new(samples, sample_rate, channels)
+    }
+
+    /// Play back a WAV file, with its samples normalized to `f32`.
+    pub fn from_wav(path: &Path) -> Result<Self, String> {
+        let reader = hound::WavReader::open(path).map_err(|err| err.to_string())?;
+        let spec = reader.spec();
+        let samples = match spec.sample_format {
+            hound::SampleFormat::Float => {
+                reader.into_samples::<f32>().collect::<Result<Vec<_>, _>>()
+            }
+            hound::SampleFormat::Int => {
+                let scale = (1u64 << (spec.bits_per_sample - 1)) as f32;
+                reader
+                    .into_samples::<i32>()
+                    .map(|sample| sample.map(|sample| sample as f32 / scale))
+                    .collect()
+            }
+        }
+        .map_err(|err| err.to_string())?;
+
+        Ok(Self::new(samples, spec.sample_rate, spec.channels as usize))
+    }
+}
+
+impl AudioSource for SyntheticSource {
+    fn sample_rate(&self) -> u32 {
+        self.sample_rate
+    }
+
+    fn channels(&self) -> usize {
+        self.channels
+    }
+
+    fn read(&mut self, buffer: &mut Vec<f32>) -> Result<usize, String> {
+        let frames = self.samples.len() / self.channels;
+        let due = (self.started.elapsed().as_secs_f64() * self.sample_rate as f64) as usize;
+        let end = due.min(frames);
+        if end <= self.position {
+            return Ok(0);
+        }
+
+        let samples = &self.samples[self.position * self.channels..end * self.channels];
+        buffer.extend_from_slice(samples);
+        self.position = end;
+        Ok(samples.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Count the zero crossings going upwards, i.e. the number of periods of a sine.
+    fn rising_zero_crossings(samples: &[f32]) -> usize {
+        samples
+            .windows(2)
+            .filter(|pair| pair[0] < 0.0 && pair[1] >= 0.0)
+            .count()
+    }
+
+    #[test]
+    fn synthetic_source_produces_audio_in_real_time() {
+        let mut source = SyntheticSource::sine(440.0, 0.5, Duration::from_secs(1), 8_000, 2);
+        let mut buffer = vec![];
+        std::thread::sleep(Duration::from_millis(100));
+        let read = source.read(&mut buffer).unwrap();
+        // At least 100 ms of stereo audio, but nowhere near the whole second.
+        assert!((1_600..8_000).contains(&read), "read {} samples", read);
+        assert_eq!(read % 2, 0);
+        assert_eq!(buffer.len(), read);
+        assert!(buffer.iter().all(|sample| sample.abs() <= 0.5));
+    }
+
+    #[test]
+    fn sweep_rises_from_start_to_end_frequency() {
+        let mut source =
+            SyntheticSource::sweep(100.0, 1_000.0, 1.0, Duration::from_secs(1), 8_000, 1);
+        // Read the whole sweep at once, as if the sweep had been playing for a while.
+        source.started -= Duration::from_secs(2);
+        let mut buffer = vec![];
+        assert_eq!(source.read(&mut buffer).unwrap(), 8_000);
+        assert_eq!(source.read(&mut buffer).unwrap(), 0);
+
+        // Over the first and last 100 ms, the sweep is close to 100 Hz and 1 kHz.
+        let start = rising_zero_crossings(&buffer[..800]);
+        let end = rising_zero_crossings(&buffer[7_200..]);
+        assert!((9..=12).contains(&start), "{} periods at the start", start);
+        assert!((85..=100).contains(&end), "{} periods at the end", end);
+    }
+
+    #[test]
+    fn wav_files_play_back_normalized() {
+        let path =
+            std::env::temp_dir().join(format!("tauri-pitch-source-{}.wav", std::process::id()));
+        let spec = hound::WavSpec {
+            channels: 2,
+            sample_rate: 8_000,
+            bits_per_sample: 16,
+            sample_format: hound::SampleFormat::Int,
+        };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..100 {
+            writer.write_sample(i16::MIN).unwrap();
+            writer.write_sample(16_384i16).unwrap();
+        }
+        writer.finalize().unwrap();
+
+        let mut source = SyntheticSource::from_wav(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(source.sample_rate(), 8_000);
+        assert_eq!(source.channels(), 2);
+
+        source.started -= Duration::from_secs(1);
+        let mut buffer = vec![];
+        assert_eq!(source.read(&mut buffer).unwrap(), 200);
+        assert_eq!(&buffer[..4], &[-1.0, 0.5, -1.0, 0.5]);
+    }
+}
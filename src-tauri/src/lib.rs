@@ -50,6 +50,10 @@ pub fn run() {
             let app_data = app_data.clone();
             move |app| {
                 app.manage(Mutex::new(app_data.clone()));
+                app.manage(audio_capture::AudioCapture::default());
+                if let Err(err) = audio_capture::load_settings(app.handle()) {
+                    eprintln!("Failed to load audio settings: {}", err);
+                }
                 // Spawn a thread to run the Yrs server
                 std::thread::spawn(move || {
                     let rt = tokio::runtime::Builder::new_current_thread()
@@ -72,6 +76,18 @@ pub fn run() {
         .invoke_handler(tauri::generate_handler![
             get_server_address::get_server_address,
             audio_capture::record_sample,
+            audio_capture::start_capture,
+            audio_capture::stop_capture,
+            audio_capture::capture_status,
+            audio_capture::get_input_level,
+            audio_capture::list_input_devices,
+            audio_capture::set_input_device,
+            audio_capture::list_jack_ports,
+            audio_capture::set_jack_port,
+            audio_capture::start_recording,
+            audio_capture::stop_recording,
+            audio_capture::list_recordings,
+            audio_capture::delete_recording,
             fetch_youtube::fetch_youtube,
             fetch_youtube::get_available_songs
         ])
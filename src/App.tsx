@@ -12,6 +12,7 @@ import {
     clarityThresholdSelector,
     currentPitchSelector,
     hostingAddressSelector,
+    pitchDetectionAlgorithmSelector,
 } from "./state/redux-slices/core";
 import { Button, Divider, HTMLSelect } from "@blueprintjs/core";
 import { CircleChart } from "./components/circle-chart/circle-chat";
@@ -26,6 +27,9 @@ function App() {
     const activeAudioDevice = useAppSelector(activeAudioDeviceSelector);
     const currentPitch = useAppSelector(currentPitchSelector);
     const clarityThreshold = useAppSelector(clarityThresholdSelector);
+    const pitchDetectionAlgorithm = useAppSelector(
+        pitchDetectionAlgorithmSelector
+    );
     const appRuntime = useAppSelector(appRuntimeSelector);
     const hostingAddress = useAppSelector(hostingAddressSelector);
 
@@ -80,7 +84,7 @@ function App() {
                                                     );
                                                     await dispatch(
                                                         coreThunks.setPitchDetectionAlgorithm(
-                                                            "autocorrelation"
+                                                            pitchDetectionAlgorithm
                                                         )
                                                     );
                                                     await dispatch(
@@ -1,11 +1,12 @@
 import React from "react";
+import type { Tuning } from "pitch-detection-wasm";
 import "./style.css";
 import {
     computeInnerAndOuterRadius,
-    freqToAngle,
-    freqToNote,
+    midiToAngle,
     radialBoxLayoutOffsets,
 } from "./utils";
+import { useTuning } from "./tuning";
 
 export type Note =
     | "C"
@@ -132,23 +133,22 @@ function drawCircleChartArrow(
     ctx.stroke();
 }
 
-function Frequency({
-    hz,
-    noteFormat,
-}: {
-    hz: number;
-    noteFormat: "sharp" | "flat";
-}) {
-    const noteSymbols =
-        noteFormat === "sharp" ? NOTE_SYMBOLS_SHARP : NOTE_SYMBOLS_FLAT;
-    const { note, octave } = freqToNote(hz);
+function Frequency({ hz, tuning }: { hz: number; tuning: Tuning | null }) {
+    let name = "";
+    let octave = "";
+    if (tuning && Number.isFinite(hz) && hz > 0) {
+        const note = tuning.nearest_note(hz);
+        // Names are a letter with its accidental, followed by the octave, e.g. "F#4".
+        const [, letter, number] = note.name.match(/^(.[#b]*)(-?\d+)$/) ?? [];
+        name = (letter ?? "").replace(/#/g, "♯").replace(/b/g, "♭");
+        octave = number ?? "";
+        note.free();
+    }
     return (
         <div className="freq-container">
             <span className="freq-note">
-                {noteSymbols[note]}
-                <span className="freq-octave">
-                    {Number.isNaN(octave) ? "" : octave}
-                </span>
+                {name}
+                <span className="freq-octave">{octave}</span>
             </span>
             <span className="freq-hz">{Math.round(hz)} Hz</span>
         </div>
@@ -247,7 +247,8 @@ export function CircleChart({
     const surroundingDivRef = React.useRef<HTMLDivElement>(null);
     const canvasPointerRef = React.useRef<HTMLCanvasElement>(null);
     const { innerR, outerR } = computeInnerAndOuterRadius(w, h);
-    const angle = freqToAngle(freq || 440);
+    const tuning = useTuning();
+    const angle = midiToAngle(tuning?.frequency_to_midi(freq || 440) ?? 69);
 
     const handleResize = React.useCallback(function handleResize() {
         const div = surroundingDivRef.current;
@@ -304,7 +305,7 @@ export function CircleChart({
     return (
         <div ref={surroundingDivRef} className="freq-surround">
             <CircleChartBackground w={w} h={h} noteFormat="sharp" />
-            {freq && <Frequency hz={freq || 440} noteFormat="sharp" />}
+            {freq && <Frequency hz={freq || 440} tuning={tuning} />}
             <canvas
                 className="freq-pointer"
                 width={w}
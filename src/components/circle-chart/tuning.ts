@@ -0,0 +1,64 @@
+import React from "react";
+import init, { Mode, Temperament, Tuning } from "pitch-detection-wasm";
+import { useAppSelector } from "../../state/hooks";
+import {
+    keySelector,
+    referencePitchSelector,
+    temperamentSelector,
+} from "../../state/redux-slices/core";
+import type { TemperamentName } from "../../worker";
+
+const TEMPERAMENTS: Record<TemperamentName, Temperament> = {
+    equal: Temperament.Equal,
+    just: Temperament.Just,
+    pythagorean: Temperament.Pythagorean,
+};
+
+/**
+ * The WASM module for the main thread. It is separate from the worker's, and only needs to be
+ * loaded once.
+ */
+let wasmInitialized: Promise<unknown> | null = null;
+
+/**
+ * A `Tuning` with the reference pitch, temperament and key from the settings, or `null` while
+ * the WASM module is loading or if the settings are invalid.
+ */
+export function useTuning(): Tuning | null {
+    const referencePitch = useAppSelector(referencePitchSelector);
+    const temperament = useAppSelector(temperamentSelector);
+    const key = useAppSelector(keySelector);
+    const [tuning, setTuning] = React.useState<Tuning | null>(null);
+
+    React.useEffect(() => {
+        let tuning: Tuning | null = null;
+        let cancelled = false;
+        if (!wasmInitialized) {
+            wasmInitialized = init();
+        }
+        wasmInitialized
+            .then(() => {
+                if (cancelled) {
+                    return;
+                }
+                tuning = Tuning.new(referencePitch);
+                tuning.set_temperament(TEMPERAMENTS[temperament]);
+                if (key) {
+                    tuning.set_key(
+                        key.tonic,
+                        key.mode === "minor" ? Mode.Minor : Mode.Major
+                    );
+                }
+                setTuning(tuning);
+            })
+            .catch((e) => console.warn("Could not create the tuning", e));
+
+        return () => {
+            cancelled = true;
+            setTuning(null);
+            tuning?.free();
+        };
+    }, [referencePitch, temperament, key]);
+
+    return tuning;
+}
@@ -1,35 +1,14 @@
-import { Note, NOTE_SYMBOLS_SHARP } from './circle-chat';
-
-export function freqToNote(freq: number): { note: Note; octave: number } {
-  const notes = Object.keys(NOTE_SYMBOLS_SHARP) as Note[];
-  const logNote = Math.log2(freq) - LOG_A;
-  // LOG_A is the log of A_4
-  const octave = Math.floor(logNote) + 4;
-  const rot = (((logNote - 0.25) % 1) + 1) % 1;
-  for (let i = 0; i < 12; i++) {
-    const low = i / 12 - 1 / 24;
-    const hi = i / 12 + 1 / 24;
-    if (rot > low && rot <= hi) {
-      return { note: notes[i], octave };
-    }
-  }
-  return { note: 'C', octave };
-}
-
-// The log of A_4
-const LOG_A = Math.log2(440);
-
 /**
- * Convert a frequency to an angle in radians. The frequency
- * of a "C" is at the top of the circle.
+ * Convert a (fractional) MIDI note number to an angle in radians. A "C"
+ * is at the top of the circle.
  *
  * @export
- * @param {number} freq
+ * @param {number} midi
  * @returns {number}
  */
-export function freqToAngle(freq: number): number {
-  // We subtract 0.5, because A is halfway around the circle chart from 0 deg.
-  return ((((Math.log2(freq) - LOG_A - 0.5) % 1) + 1) % 1) * Math.PI * 2;
+export function midiToAngle(midi: number): number {
+  // We add 0.75, because C is three quarters of a turn clockwise from 0 deg.
+  return ((((midi / 12 + 0.75) % 1) + 1) % 1) * Math.PI * 2;
 }
 
 /**
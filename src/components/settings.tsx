@@ -1,19 +1,100 @@
-import { Card, H3 } from "@blueprintjs/core";
-import { useAppSelector } from "../state/hooks";
+import {
+    Card,
+    Classes,
+    H3,
+    HTMLSelect,
+    NumericInput,
+} from "@blueprintjs/core";
+import { useAppDispatch, useAppSelector } from "../state/hooks";
 import {
     appRuntimeSelector,
     hostingAddressSelector,
+    keySelector,
+    MusicalKey,
+    nativeCaptureSelector,
+    pitchDetectionAlgorithmSelector,
+    referencePitchSelector,
+    temperamentSelector,
 } from "../state/redux-slices/core";
+import { coreThunks } from "../state/redux-slices/core/thunks";
+import type { DetectorName, TemperamentName } from "../worker";
 import React from "react";
 import { appDataDir, join } from "@tauri-apps/api/path";
+import { invoke } from "@tauri-apps/api/core";
+
+/**
+ * An input device as reported by the backend's `list_input_devices` command.
+ */
+type InputDeviceInfo = {
+    name: string;
+    is_default: boolean;
+    is_selected: boolean;
+    configs: {
+        channels: number;
+        min_sample_rate: number;
+        max_sample_rate: number;
+        sample_format: string;
+    }[];
+};
+
+const DEFAULT_DEVICE_OPTION = "";
+
+const NO_KEY_OPTION = "";
+const TONICS = [
+    "C",
+    "C♯/D♭",
+    "D",
+    "E♭",
+    "E",
+    "F",
+    "F♯/G♭",
+    "G",
+    "A♭",
+    "A",
+    "B♭",
+    "B",
+];
+
+const TEMPERAMENTS: { value: TemperamentName; label: string }[] = [
+    { value: "equal", label: "Equal temperament" },
+    { value: "just", label: "Just intonation" },
+    { value: "pythagorean", label: "Pythagorean" },
+];
+
+/**
+ * The pitch detection algorithms, and whether the backend can run them. Algorithms the backend
+ * can't run are only available when pitches are detected in the browser, which is the case
+ * unless the webview isn't allowed to use the microphone.
+ */
+const PITCH_ALGORITHMS: {
+    value: DetectorName;
+    label: string;
+    native: boolean;
+}[] = [
+    { value: "autocorrelation", label: "Autocorrelation", native: true },
+    { value: "mcleod", label: "McLeod", native: true },
+    { value: "yin", label: "YIN", native: false },
+    { value: "pyin", label: "Probabilistic YIN", native: false },
+];
 
 /**
  * Show all the settings for the app.
  */
 export function Settings() {
+    const dispatch = useAppDispatch();
     const hostingAddress = useAppSelector(hostingAddressSelector);
     const appRuntime = useAppSelector(appRuntimeSelector);
+    const pitchDetectionAlgorithm = useAppSelector(
+        pitchDetectionAlgorithmSelector
+    );
+    const nativeCapture = useAppSelector(nativeCaptureSelector);
+    const referencePitch = useAppSelector(referencePitchSelector);
+    const temperament = useAppSelector(temperamentSelector);
+    const key = useAppSelector(keySelector);
     const [dataDir, setDataDir] = React.useState<string | null>(null);
+    const [inputDevices, setInputDevices] = React.useState<InputDeviceInfo[]>(
+        []
+    );
 
     React.useEffect(() => {
         if (appRuntime !== "tauri") {
@@ -24,8 +105,39 @@ export function Settings() {
             setDataDir(await join(dir, "youtube_downloads"));
         };
         fetchDataDir();
+        invoke<InputDeviceInfo[]>("list_input_devices")
+            .then(setInputDevices)
+            .catch((e) => console.warn("Could not list input devices", e));
     }, [appRuntime]);
 
+    const selectedDevice =
+        inputDevices.find((d) => d.is_selected)?.name ??
+        DEFAULT_DEVICE_OPTION;
+    const selectInputDevice = async (name: string) => {
+        await invoke("set_input_device", {
+            name: name === DEFAULT_DEVICE_OPTION ? null : name,
+        });
+        setInputDevices(
+            await invoke<InputDeviceInfo[]>("list_input_devices")
+        );
+    };
+
+    const setTuning = (
+        tuning: Partial<{
+            referencePitch: number;
+            temperament: TemperamentName;
+            key: MusicalKey | null;
+        }>
+    ) =>
+        dispatch(
+            coreThunks.setTuning({
+                referencePitch,
+                temperament,
+                key,
+                ...tuning,
+            })
+        );
+
     return (
         <div className="settings-container">
             <Card>
@@ -74,6 +186,124 @@ export function Settings() {
                     </p>
                 )}
             </Card>
+            <Card>
+                <H3>Pitch Detection</H3>
+                <p>
+                    The algorithm used to detect the pitch being sung. YIN and
+                    probabilistic YIN are more accurate for voices, but can't be
+                    used when audio is captured by the backend.
+                </p>
+                <HTMLSelect
+                    value={pitchDetectionAlgorithm}
+                    onChange={(e) =>
+                        dispatch(
+                            coreThunks.setPitchDetectionAlgorithm(
+                                e.currentTarget.value as DetectorName
+                            )
+                        )
+                    }
+                >
+                    {PITCH_ALGORITHMS.map((algorithm) => (
+                        <option
+                            key={algorithm.value}
+                            value={algorithm.value}
+                            disabled={nativeCapture && !algorithm.native}
+                        >
+                            {algorithm.label}
+                        </option>
+                    ))}
+                </HTMLSelect>
+            </Card>
+            <Card>
+                <H3>Tuning</H3>
+                <p>
+                    The frequency of A4, the temperament, and the key that
+                    notes are spelled for and that just intonation is built
+                    on.
+                </p>
+                <NumericInput
+                    value={referencePitch}
+                    min={415}
+                    max={466}
+                    rightElement={
+                        <span className={Classes.TEXT_MUTED}>Hz</span>
+                    }
+                    onValueChange={(value) => {
+                        if (value >= 415 && value <= 466) {
+                            setTuning({ referencePitch: value });
+                        }
+                    }}
+                />
+                <HTMLSelect
+                    value={temperament}
+                    onChange={(e) =>
+                        setTuning({
+                            temperament: e.currentTarget
+                                .value as TemperamentName,
+                        })
+                    }
+                >
+                    {TEMPERAMENTS.map((temperament) => (
+                        <option
+                            key={temperament.value}
+                            value={temperament.value}
+                        >
+                            {temperament.label}
+                        </option>
+                    ))}
+                </HTMLSelect>
+                <HTMLSelect
+                    value={key ? `${key.tonic}:${key.mode}` : NO_KEY_OPTION}
+                    onChange={(e) => {
+                        const [tonic, mode] = e.currentTarget.value.split(":");
+                        setTuning({
+                            key: mode
+                                ? {
+                                      tonic: Number(tonic),
+                                      mode: mode as MusicalKey["mode"],
+                                  }
+                                : null,
+                        });
+                    }}
+                >
+                    <option value={NO_KEY_OPTION}>No key</option>
+                    {(["major", "minor"] as const).flatMap((mode) =>
+                        TONICS.map((tonic, i) => (
+                            <option
+                                key={`${i}:${mode}`}
+                                value={`${i}:${mode}`}
+                            >
+                                {tonic} {mode}
+                            </option>
+                        ))
+                    )}
+                </HTMLSelect>
+            </Card>
+            {appRuntime === "tauri" && (
+                <Card>
+                    <H3>Input Device</H3>
+                    <p>
+                        The microphone used when audio is captured by the
+                        backend.
+                    </p>
+                    <HTMLSelect
+                        value={selectedDevice}
+                        onChange={(e) =>
+                            selectInputDevice(e.currentTarget.value)
+                        }
+                    >
+                        <option value={DEFAULT_DEVICE_OPTION}>
+                            System default
+                        </option>
+                        {inputDevices.map((device) => (
+                            <option key={device.name} value={device.name}>
+                                {device.name}
+                                {device.is_default ? " (default)" : ""}
+                            </option>
+                        ))}
+                    </HTMLSelect>
+                </Card>
+            )}
         </div>
     );
 }
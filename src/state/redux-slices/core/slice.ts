@@ -1,6 +1,12 @@
 import { createSlice } from "@reduxjs/toolkit";
 import type { PayloadAction } from "@reduxjs/toolkit";
 import type { RootState } from "../../store";
+import type { DetectorName, TemperamentName } from "../../../worker";
+
+/**
+ * A key to spell notes for. `tonic` is a pitch class, `0` for C to `11` for B.
+ */
+export type MusicalKey = { tonic: number; mode: "major" | "minor" };
 
 export interface CoreState {
     /**
@@ -11,7 +17,7 @@ export interface CoreState {
      * The IP address/port of the hosting server. `undefined` if we're not hosting.
      */
     hostingAddress?: string;
-    pitchDetectionAlgorithm: "autocorrelation" | "mcleod";
+    pitchDetectionAlgorithm: DetectorName;
     windowSize: number;
     /**
      * The threshold of confidence for a detected pitch to be displayed.
@@ -22,8 +28,26 @@ export interface CoreState {
      */
     powerThreshold: number;
     currentPitch: { pitch: number; clarity: number };
+    /**
+     * The frequency of A4 in Hz, from 415 to 466.
+     */
+    referencePitch: number;
+    /**
+     * The temperament notes are tuned in. Just and Pythagorean tuning are built on the tonic of
+     * `key`, or on C without a key.
+     */
+    temperament: TemperamentName;
+    /**
+     * The key notes are spelled for. Without a key, notes are spelled with sharps.
+     */
+    key: MusicalKey | null;
 
     activeAudioDevice: string | null;
+    /**
+     * Whether audio is captured and pitched by the backend rather than in the browser. This is
+     * the fallback when the webview isn't allowed to use the microphone.
+     */
+    nativeCapture: boolean;
 
     workerCacheKey?: number;
     inErrorState: boolean;
@@ -43,12 +67,16 @@ const initialState: CoreState = {
         // Otherwise, we assume the hosting address is the same as the origin
         return origin;
     })(),
-    pitchDetectionAlgorithm: "mcleod",
+    pitchDetectionAlgorithm: "autocorrelation",
     windowSize: 2048,
     clarityThreshold: 0.5,
     powerThreshold: 0.015,
     currentPitch: { pitch: 0, clarity: 0 },
+    referencePitch: 440,
+    temperament: "equal",
+    key: null,
     activeAudioDevice: null,
+    nativeCapture: false,
 
     workerCacheKey: undefined,
     inErrorState: false,
@@ -60,7 +88,7 @@ const coreSlice = createSlice({
     reducers: {
         _setPitchDetectionAlgorithm: (
             state,
-            action: PayloadAction<"autocorrelation" | "mcleod">
+            action: PayloadAction<DetectorName>
         ) => {
             state.pitchDetectionAlgorithm = action.payload;
         },
@@ -79,10 +107,23 @@ const coreSlice = createSlice({
         ) => {
             state.currentPitch = action.payload;
         },
+        _setReferencePitch: (state, action: PayloadAction<number>) => {
+            state.referencePitch = action.payload;
+        },
+        _setTemperament: (state, action: PayloadAction<TemperamentName>) => {
+            state.temperament = action.payload;
+        },
+        _setKey: (state, action: PayloadAction<MusicalKey | null>) => {
+            state.key = action.payload;
+        },
         setActiveAudioDevice: (state, action: PayloadAction<string | null>) => {
             state.activeAudioDevice = action.payload;
         },
 
+        _setNativeCapture: (state, action: PayloadAction<boolean>) => {
+            state.nativeCapture = action.payload;
+        },
+
         _setWorkerCacheKey: (state, action: PayloadAction<number>) => {
             state.workerCacheKey = action.payload;
         },
@@ -113,9 +154,23 @@ export const activeAudioDeviceSelector = (state: RootState) =>
 export const currentPitchSelector = (state: RootState) =>
     selfSelector(state).currentPitch;
 
+export const pitchDetectionAlgorithmSelector = (state: RootState) =>
+    selfSelector(state).pitchDetectionAlgorithm;
+
 export const clarityThresholdSelector = (state: RootState) =>
     selfSelector(state).clarityThreshold;
 
+export const referencePitchSelector = (state: RootState) =>
+    selfSelector(state).referencePitch;
+
+export const temperamentSelector = (state: RootState) =>
+    selfSelector(state).temperament;
+
+export const keySelector = (state: RootState) => selfSelector(state).key;
+
+export const nativeCaptureSelector = (state: RootState) =>
+    selfSelector(state).nativeCapture;
+
 export const appRuntimeSelector = (state: RootState) =>
     selfSelector(state).appRuntime;
 
@@ -1,10 +1,15 @@
 import * as Comlink from "comlink";
 import { createLoggingAsyncThunk } from "../../hooks";
-import { _coreReducerActions, selfSelector } from "./slice";
+import { _coreReducerActions, MusicalKey, selfSelector } from "./slice";
 
 import PitchWorker from "../../../worker?worker";
-import { PitchWorker as PitchWorkerClass } from "../../../worker";
+import {
+    DetectorName,
+    PitchWorker as PitchWorkerClass,
+    TemperamentName,
+} from "../../../worker";
 import { invoke } from "@tauri-apps/api/core";
+import { listen, UnlistenFn } from "@tauri-apps/api/event";
 
 type PitchSetup = {
     analyser?: AnalyserNode;
@@ -13,12 +18,25 @@ type PitchSetup = {
     buffer: Float32Array;
 };
 
+/**
+ * A pitch detected by the backend's capture session. A `frequency` of `-1` means no pitch was found.
+ */
+type PitchEvent = {
+    frequency: number;
+    clarity: number;
+    timestamp: number;
+};
+
 /**
  * The worker instance. The app only uses one worker instance; it is reused for all processing.
  */
 let worker: Comlink.Remote<PitchWorkerClass> | null = null;
 let stream: MediaStream | "tauri" | null = null;
 let activeTimeouts: ReturnType<typeof setInterval>[] = [];
+/**
+ * Unsubscribes from the backend's `audio:pitch` events while a Tauri capture session is running.
+ */
+let unlistenPitches: UnlistenFn | null = null;
 
 export const coreThunks = {
     /**
@@ -31,6 +49,7 @@ export const coreThunks = {
                 stream = await navigator.mediaDevices.getUserMedia({
                     audio: { echoCancellation: true, autoGainControl: true },
                 });
+                dispatch(_coreReducerActions._setNativeCapture(false));
                 // If we made it this far, our user has granted us permission to use the microphone.
                 // Grab the device name for this device.
                 const audioTracks = stream.getAudioTracks();
@@ -54,6 +73,7 @@ export const coreThunks = {
                         "Using Tauri as a fallback for audio processing"
                     );
                     stream = "tauri";
+                    dispatch(_coreReducerActions._setNativeCapture(true));
                     dispatch(
                         _coreReducerActions.setActiveAudioDevice(
                             "Backend (Default Input Device)"
@@ -95,10 +115,7 @@ export const coreThunks = {
      */
     setPitchDetectionAlgorithm: createLoggingAsyncThunk(
         "core/setPitchDetectionAlgorithm",
-        async (
-            algorithm: "autocorrelation" | "mcleod",
-            { dispatch, getState }
-        ) => {
+        async (algorithm: DetectorName, { dispatch, getState }) => {
             dispatch(
                 _coreReducerActions._setPitchDetectionAlgorithm(algorithm)
             );
@@ -130,6 +147,36 @@ export const coreThunks = {
             }
         }
     ),
+    /**
+     * Set the reference pitch, temperament and key that notes are named with, in the app and in
+     * the worker.
+     */
+    setTuning: createLoggingAsyncThunk(
+        "core/setTuning",
+        async (
+            {
+                referencePitch,
+                temperament,
+                key,
+            }: {
+                referencePitch: number;
+                temperament: TemperamentName;
+                key: MusicalKey | null;
+            },
+            { dispatch }
+        ) => {
+            dispatch(_coreReducerActions._setReferencePitch(referencePitch));
+            dispatch(_coreReducerActions._setTemperament(temperament));
+            dispatch(_coreReducerActions._setKey(key));
+            if (worker) {
+                await worker.setTuning(
+                    referencePitch,
+                    temperament,
+                    key ?? undefined
+                );
+            }
+        }
+    ),
 
     /*
      * Start collecting pitch data.
@@ -137,16 +184,47 @@ export const coreThunks = {
     collectPitches: createLoggingAsyncThunk(
         "core/collectPitches",
         async (_: void, { dispatch, getState }) => {
-            const { windowSize, clarityThreshold, powerThreshold } =
-                selfSelector(getState());
-            if (!worker) {
+            const {
+                windowSize,
+                clarityThreshold,
+                powerThreshold,
+                pitchDetectionAlgorithm,
+            } = selfSelector(getState());
+            if (!stream) {
                 throw new Error(
-                    "Worker must be initialized before collecting pitches"
+                    "Audio stream must be initialized before collecting pitches"
                 );
             }
-            if (!stream) {
+
+            if (stream === "tauri") {
+                // The backend keeps the input device open and detects the pitch of every
+                // `windowSize` samples itself, so no worker is needed.
+                unlistenPitches?.();
+                unlistenPitches = await listen<PitchEvent>(
+                    "audio:pitch",
+                    (event) => {
+                        dispatch(
+                            _coreReducerActions.setCurrentPitch({
+                                pitch: event.payload.frequency,
+                                clarity: event.payload.clarity,
+                            })
+                        );
+                    }
+                );
+                await invoke("start_capture", {
+                    frameSize: windowSize,
+                    pitchDetection: {
+                        algorithm: pitchDetectionAlgorithm,
+                        power_threshold: powerThreshold,
+                        clarity_threshold: clarityThreshold,
+                    },
+                    emitFrames: false,
+                });
+                return;
+            }
+            if (!worker) {
                 throw new Error(
-                    "Audio stream must be initialized before collecting pitches"
+                    "Worker must be initialized before collecting pitches"
                 );
             }
 
@@ -154,69 +232,43 @@ export const coreThunks = {
                 sample_rate: number;
                 data: Float32Array;
             }> {
-                if (!stream) {
+                if (!stream || stream === "tauri") {
                     throw new Error("Audio stream is not initialized");
                 }
-                if (stream === "tauri") {
-                    // We use the Tauri API to grab audio samples.
-                    return async () => {
-                        const samp = (await invoke("record_sample", {
-                            interval: 50,
-                        })) as [number, number[]];
+                // We use the browser's MediaStream API to grab audio samples.
+                let pitchSetup: PitchSetup = {
+                    buffer: new Float32Array(windowSize),
+                    audioContext: new AudioContext(),
+                };
 
-                        let rawData = samp[1];
-                        if (rawData.length > windowSize) {
-                            rawData = rawData.slice(0, windowSize);
-                        } else if (rawData.length < windowSize) {
-                            // If the data is shorter than the window size, pad it with zeros.
-                            const padding = new Array(
-                                windowSize - rawData.length
-                            ).fill(0);
-                            rawData = rawData.concat(padding);
-                        }
+                // Create an AudioNode from the stream.
+                const mediaStreamSource =
+                    pitchSetup.audioContext.createMediaStreamSource(stream);
 
-                        const ret = {
-                            sample_rate: samp[0],
-                            data: new Float32Array(rawData),
-                        };
-                        return ret;
-                    };
-                } else {
-                    // We use the browser's MediaStream API to grab audio samples.
-                    let pitchSetup: PitchSetup = {
-                        buffer: new Float32Array(windowSize),
-                        audioContext: new AudioContext(),
-                    };
-
-                    // Create an AudioNode from the stream.
-                    const mediaStreamSource =
-                        pitchSetup.audioContext.createMediaStreamSource(stream);
+                // Connect it to the destination.
+                pitchSetup.analyser =
+                    pitchSetup.audioContext.createAnalyser();
+                pitchSetup.analyser.fftSize = windowSize;
+                mediaStreamSource.connect(pitchSetup.analyser);
 
-                    // Connect it to the destination.
-                    pitchSetup.analyser =
-                        pitchSetup.audioContext.createAnalyser();
-                    pitchSetup.analyser.fftSize = windowSize;
-                    mediaStreamSource.connect(pitchSetup.analyser);
-
-                    return async () => {
-                        if (!pitchSetup.analyser) {
-                            console.warn(
-                                "Trying to update the pitch, but missing an analyser"
-                            );
-                            return {
-                                sample_rate: pitchSetup.audioContext.sampleRate,
-                                data: pitchSetup.buffer,
-                            };
-                        }
-                        const { analyser, buffer, audioContext } = pitchSetup;
-                        analyser.getFloatTimeDomainData(buffer);
-                        const ret = {
-                            sample_rate: audioContext.sampleRate,
-                            data: buffer,
+                return async () => {
+                    if (!pitchSetup.analyser) {
+                        console.warn(
+                            "Trying to update the pitch, but missing an analyser"
+                        );
+                        return {
+                            sample_rate: pitchSetup.audioContext.sampleRate,
+                            data: pitchSetup.buffer,
                         };
-                        return ret;
+                    }
+                    const { analyser, buffer, audioContext } = pitchSetup;
+                    analyser.getFloatTimeDomainData(buffer);
+                    const ret = {
+                        sample_rate: audioContext.sampleRate,
+                        data: buffer,
                     };
-                }
+                    return ret;
+                };
             }
 
             const grabSample = grabSampleFactory();
@@ -258,8 +310,10 @@ export const coreThunks = {
             if (stream && stream !== "tauri") {
                 stream.getTracks().forEach((track) => track.stop());
                 stream = null;
-            } else {
-                // If the `stream` type is "tauri", there is no need to clean up.
+            } else if (stream === "tauri") {
+                unlistenPitches?.();
+                unlistenPitches = null;
+                await invoke("stop_capture");
             }
             activeTimeouts.forEach((timeoutId) => clearInterval(timeoutId));
             activeTimeouts.length = 0;
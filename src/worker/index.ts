@@ -1,13 +1,63 @@
 import init, {
+    analyze_pitch,
+    analyze_vibrato,
     AutocorrelationDetector,
     McLeodDetector,
+    Mode,
+    MultiPitchDetector,
+    OnsetDetector,
+    PitchAlgorithm,
+    PyinDetector,
+    SpectrogramAnalyzer,
+    StreamingDetector,
+    Temperament,
+    Tuning,
+    WindowFunction,
+    YinDetector,
 } from "pitch-detection-wasm";
 import * as Comlink from "comlink";
 
+export type DetectorName = "autocorrelation" | "mcleod" | "yin" | "pyin";
+
+const ALGORITHMS: Record<DetectorName, PitchAlgorithm> = {
+    autocorrelation: PitchAlgorithm.Autocorrelation,
+    mcleod: PitchAlgorithm.McLeod,
+    yin: PitchAlgorithm.Yin,
+    pyin: PitchAlgorithm.Pyin,
+};
+
+export type TemperamentName = "equal" | "just" | "pythagorean";
+
+const TEMPERAMENTS: Record<TemperamentName, Temperament> = {
+    equal: Temperament.Equal,
+    just: Temperament.Just,
+    pythagorean: Temperament.Pythagorean,
+};
+
+export type WindowName = "rectangular" | "hann" | "hamming" | "blackman";
+
+const WINDOWS: Record<WindowName, WindowFunction> = {
+    rectangular: WindowFunction.Rectangular,
+    hann: WindowFunction.Hann,
+    hamming: WindowFunction.Hamming,
+    blackman: WindowFunction.Blackman,
+};
+
 export class PitchWorker {
     wasmInitialized = Promise.resolve(false);
     //   wasm?: InitOutput;
-    detector?: AutocorrelationDetector | McLeodDetector;
+    detector?:
+        | AutocorrelationDetector
+        | McLeodDetector
+        | YinDetector
+        | PyinDetector;
+    multiDetector?: MultiPitchDetector;
+    onsetDetector?: OnsetDetector;
+    spectrogramAnalyzer?: SpectrogramAnalyzer;
+    tuning?: Tuning;
+    streamingDetector?: StreamingDetector;
+    streamingHopSize = 1;
+    memory?: WebAssembly.Memory;
 
     /**
      * Initialize the WASM module. This only needs to happen once.
@@ -23,7 +73,8 @@ export class PitchWorker {
             resolve = res;
         });
         try {
-            await init();
+            const wasm = await init();
+            this.memory = wasm.memory;
             resolve!(true);
         } finally {
             // It is okay to resolve twice. The second call to resolve will be ignored.
@@ -32,7 +83,7 @@ export class PitchWorker {
     }
 
     async setDetector(
-        name: "autocorrelation" | "mcleod",
+        name: DetectorName,
         size: number,
         padding: number
     ) {
@@ -52,9 +103,221 @@ export class PitchWorker {
             case "mcleod":
                 this.detector = McLeodDetector.new(size, padding);
                 break;
+            case "yin":
+                this.detector = YinDetector.new(size, padding);
+                break;
+            case "pyin":
+                this.detector = PyinDetector.new(size, padding);
+                break;
             default:
                 throw new Error(`Detector type not recognized: ${name}`);
         }
+        if (this.tuning) {
+            this.detector.set_tuning(this.tuning);
+        }
+    }
+
+    /**
+     * Set up the multi-pitch detector used by `getPitches`. Windows need to be longer than for
+     * the other detectors, e.g. 4096 samples at 44.1 kHz. Throws if `size` or `sampleRate` is
+     * zero.
+     */
+    async setMultiPitchDetector(
+        size: number,
+        padding: number,
+        sampleRate: number
+    ) {
+        await this.init();
+        if (!(await this.wasmInitialized)) {
+            throw new Error("WASM could not be initialized");
+        }
+        const multiDetector = MultiPitchDetector.new(size, padding, sampleRate);
+        if (this.multiDetector) {
+            this.multiDetector.free();
+        }
+        this.multiDetector = multiDetector;
+    }
+
+    /**
+     * Set the reference pitch for A4 (415 to 466 Hz), temperament and key used to name notes.
+     * A temperament can also be given as the cents above the tonic of each of the twelve
+     * semitones. `tonic` is a pitch class, `0` for C to `11` for B; without a key, notes are
+     * spelled with sharps.
+     */
+    async setTuning(
+        reference: number,
+        temperament: TemperamentName | number[],
+        key?: { tonic: number; mode: "major" | "minor" }
+    ) {
+        await this.init();
+        if (!(await this.wasmInitialized)) {
+            throw new Error("WASM could not be initialized");
+        }
+        const tuning = Tuning.new(reference);
+        if (Array.isArray(temperament)) {
+            tuning.set_custom_temperament(new Float32Array(temperament));
+        } else if (TEMPERAMENTS[temperament] !== undefined) {
+            tuning.set_temperament(TEMPERAMENTS[temperament]);
+        } else {
+            tuning.free();
+            throw new Error(`Temperament not recognized: ${temperament}`);
+        }
+        if (key) {
+            tuning.set_key(
+                key.tonic,
+                key.mode === "minor" ? Mode.Minor : Mode.Major
+            );
+        }
+
+        if (this.tuning) {
+            this.tuning.free();
+        }
+        this.tuning = tuning;
+        this.detector?.set_tuning(tuning);
+    }
+
+    /**
+     * The note closest to `frequency` in the current tuning, or with A4 at 440 Hz in equal
+     * temperament if no tuning has been set.
+     */
+    async nearestNote(frequency: number): Promise<{
+        midi: number;
+        name: string;
+        cents: number;
+        frequency: number;
+    }> {
+        await this.init();
+        if (!(await this.wasmInitialized)) {
+            throw new Error("WASM could not be initialized");
+        }
+
+        const tuning = this.tuning ?? Tuning.new(440);
+        const note = tuning.nearest_note(frequency);
+        try {
+            return {
+                midi: note.midi,
+                name: note.name,
+                cents: note.cents,
+                frequency: note.frequency,
+            };
+        } finally {
+            note.free();
+            if (tuning !== this.tuning) {
+                tuning.free();
+            }
+        }
+    }
+
+    /**
+     * Start detecting the pitch of a new stream of audio with `streamPitch`. The detector
+     * keeps the stream in a ring buffer of `capacity` samples inside WASM memory.
+     */
+    async setStreamingDetector(
+        name: DetectorName,
+        windowSize: number,
+        hopSize: number,
+        sampleRate: number,
+        powerThreshold: number,
+        clarityThreshold: number,
+        capacity: number
+    ) {
+        await this.init();
+        if (!(await this.wasmInitialized)) {
+            throw new Error("WASM could not be initialized");
+        }
+        const algorithm = ALGORITHMS[name];
+        if (algorithm === undefined) {
+            throw new Error(`Detector type not recognized: ${name}`);
+        }
+        if (this.streamingDetector) {
+            this.streamingDetector.free();
+        }
+        this.streamingHopSize = Math.max(1, hopSize);
+        this.streamingDetector = StreamingDetector.new(
+            algorithm,
+            windowSize,
+            hopSize,
+            sampleRate,
+            powerThreshold,
+            clarityThreshold,
+            capacity
+        );
+    }
+
+    /**
+     * Add the next chunk of the stream, writing `[frequency, clarity]` for every window it
+     * completes into `pitches`, one pair after the other. `pitches` needs room for
+     * `2 * Math.ceil(chunk.length / hopSize)` values.
+     *
+     * Pass each array with `Comlink.transfer(array, [array.buffer])` so that it is moved to the
+     * worker instead of cloned. Both are moved back with the number of windows found, to be
+     * reused for the next chunk.
+     *
+     * This saves the allocations `getPitch` makes in WASM memory on every call, but not the
+     * copies: WASM memory isn't a `SharedArrayBuffer`, so the caller can't write into the ring
+     * buffer directly. The samples are copied once, from `chunk` into the ring buffer, and the
+     * results once, from the output buffer into `pitches`.
+     */
+    async streamPitch(
+        chunk: Float32Array,
+        pitches: Float32Array
+    ): Promise<{ chunk: Float32Array; pitches: Float32Array; count: number }> {
+        await this.init();
+        if (!(await this.wasmInitialized)) {
+            throw new Error("WASM could not be initialized");
+        }
+        const detector = this.streamingDetector;
+        const memory = this.memory;
+        if (!detector || !memory) {
+            throw new Error(
+                "Streaming detector must be initialized before streaming"
+            );
+        }
+        if (
+            pitches.length <
+            2 * Math.ceil(chunk.length / this.streamingHopSize)
+        ) {
+            throw new Error(
+                "Pitches buffer is too small for every window of the chunk"
+            );
+        }
+
+        let count = 0;
+        let read = 0;
+        while (read < chunk.length) {
+            // The views are made on every pass, as growing WASM memory detaches old ones.
+            const capacity = detector.capacity();
+            const input = new Float32Array(
+                memory.buffer,
+                detector.input_ptr(),
+                capacity
+            );
+            const offset = detector.write_offset();
+            const written = Math.min(
+                chunk.length - read,
+                detector.writable(),
+                capacity - offset
+            );
+            input.set(chunk.subarray(read, read + written), offset);
+            detector.commit(written);
+            read += written;
+
+            const windows = detector.process_available();
+            if (windows > 0) {
+                const output = new Float32Array(
+                    memory.buffer,
+                    detector.output_ptr(),
+                    2 * windows
+                );
+                pitches.set(output, 2 * count);
+                count += windows;
+            }
+        }
+
+        return Comlink.transfer({ chunk, pitches, count }, [
+            chunk.buffer,
+            pitches.buffer,
+        ]);
     }
 
     async getPitch(
@@ -84,6 +347,278 @@ export class PitchWorker {
 
         return result;
     }
+
+    /**
+     * Detect the pitch of a single window, along with the note it is closest to in the
+     * tuning set by `setTuning`.
+     */
+    async detectPitch(
+        signal: Float32Array,
+        sampleRate: number,
+        powerThreshold: number,
+        clarityThreshold: number
+    ): Promise<{
+        frequency: number;
+        clarity: number;
+        midi: number;
+        noteName: string;
+        cents: number;
+        rms: number;
+        voiced: boolean;
+    }> {
+        await this.init();
+        if (!(await this.wasmInitialized)) {
+            throw new Error("WASM could not be initialized");
+        }
+        if (!this.detector) {
+            throw new Error(
+                "Detector must be initialized before getting pitch"
+            );
+        }
+
+        const result = this.detector.detect(
+            signal,
+            sampleRate,
+            powerThreshold,
+            clarityThreshold
+        );
+        try {
+            return {
+                frequency: result.frequency,
+                clarity: result.clarity,
+                midi: result.midi,
+                noteName: result.note_name,
+                cents: result.cents,
+                rms: result.rms,
+                voiced: result.voiced,
+            };
+        } finally {
+            result.free();
+        }
+    }
+
+    /**
+     * Detect up to `maxPitches` simultaneous pitches in a window, e.g. both singers of a duet.
+     * Pitches come strongest first, with the fraction of the window's energy each explains.
+     */
+    async getPitches(
+        signal: Float32Array,
+        powerThreshold: number,
+        salienceThreshold: number,
+        maxPitches: number
+    ): Promise<{ frequencies: Float32Array; saliences: Float32Array }> {
+        await this.init();
+        if (!(await this.wasmInitialized)) {
+            throw new Error("WASM could not be initialized");
+        }
+        if (!this.multiDetector) {
+            throw new Error(
+                "Multi-pitch detector must be initialized before getting pitches"
+            );
+        }
+
+        const pitches = this.multiDetector.get_pitches(
+            signal,
+            powerThreshold,
+            salienceThreshold,
+            maxPitches
+        );
+        try {
+            return {
+                frequencies: pitches.frequencies,
+                saliences: pitches.saliences,
+            };
+        } finally {
+            pitches.free();
+        }
+    }
+
+    /**
+     * Start detecting onsets in a new stream of audio. Timestamps passed back by `detectOnsets`
+     * are in seconds since this call.
+     */
+    async setOnsetDetector(
+        windowSize: number,
+        hopSize: number,
+        sampleRate: number,
+        powerThreshold: number
+    ) {
+        await this.init();
+        if (!(await this.wasmInitialized)) {
+            throw new Error("WASM could not be initialized");
+        }
+        if (this.onsetDetector) {
+            this.onsetDetector.free();
+        }
+        this.onsetDetector = OnsetDetector.new(
+            windowSize,
+            hopSize,
+            sampleRate,
+            powerThreshold
+        );
+    }
+
+    /**
+     * Add the next chunk of the stream, returning the times of any note onsets found in it.
+     * Onsets are reported a window and a little more after they happen.
+     */
+    async detectOnsets(chunk: Float32Array): Promise<Float64Array> {
+        await this.init();
+        if (!(await this.wasmInitialized)) {
+            throw new Error("WASM could not be initialized");
+        }
+        if (!this.onsetDetector) {
+            throw new Error(
+                "Onset detector must be initialized before detecting onsets"
+            );
+        }
+
+        return this.onsetDetector.process(chunk);
+    }
+
+    /**
+     * Start computing the spectrogram and chroma of a new stream of audio, or of a whole
+     * recording passed to `analyzeSpectrogram` in one go.
+     */
+    async setSpectrogramAnalyzer(
+        windowSize: number,
+        hopSize: number,
+        sampleRate: number,
+        window: WindowName
+    ) {
+        await this.init();
+        if (!(await this.wasmInitialized)) {
+            throw new Error("WASM could not be initialized");
+        }
+        const windowFunction = WINDOWS[window];
+        if (windowFunction === undefined) {
+            throw new Error(`Window function not recognized: ${window}`);
+        }
+        if (this.spectrogramAnalyzer) {
+            this.spectrogramAnalyzer.free();
+        }
+        this.spectrogramAnalyzer = SpectrogramAnalyzer.new(
+            windowSize,
+            hopSize,
+            sampleRate,
+            windowFunction
+        );
+    }
+
+    /**
+     * Add the next chunk of audio, returning the frames it completes. Frame `i`'s magnitudes
+     * are `magnitudes.subarray(i * bins, (i + 1) * bins)` and its chroma, from C to B, is
+     * `chroma.subarray(i * 12, (i + 1) * 12)`.
+     */
+    async analyzeSpectrogram(chunk: Float32Array): Promise<{
+        bins: number;
+        binWidth: number;
+        times: Float64Array;
+        magnitudes: Float32Array;
+        chroma: Float32Array;
+    }> {
+        await this.init();
+        if (!(await this.wasmInitialized)) {
+            throw new Error("WASM could not be initialized");
+        }
+        if (!this.spectrogramAnalyzer) {
+            throw new Error(
+                "Spectrogram analyzer must be initialized before analyzing audio"
+            );
+        }
+
+        const spectrogram = this.spectrogramAnalyzer.process(chunk);
+        try {
+            return {
+                bins: spectrogram.bins,
+                binWidth: spectrogram.bin_width,
+                times: spectrogram.times,
+                magnitudes: spectrogram.magnitudes,
+                chroma: spectrogram.chroma,
+            };
+        } finally {
+            spectrogram.free();
+        }
+    }
+
+    /**
+     * Detect the pitch of every window of a whole buffer, e.g. a recording, in one call.
+     * Windows without a pitch have a frequency of `-1`.
+     */
+    async analyzePitch(
+        signal: Float32Array,
+        sampleRate: number,
+        name: DetectorName,
+        windowSize: number,
+        hopSize: number,
+        powerThreshold: number,
+        clarityThreshold: number
+    ): Promise<{
+        times: Float64Array;
+        frequencies: Float32Array;
+        clarities: Float32Array;
+    }> {
+        await this.init();
+        if (!(await this.wasmInitialized)) {
+            throw new Error("WASM could not be initialized");
+        }
+        const algorithm = ALGORITHMS[name];
+        if (algorithm === undefined) {
+            throw new Error(`Detector type not recognized: ${name}`);
+        }
+
+        const contour = analyze_pitch(
+            signal,
+            sampleRate,
+            algorithm,
+            windowSize,
+            hopSize,
+            powerThreshold,
+            clarityThreshold
+        );
+        try {
+            return {
+                times: contour.times,
+                frequencies: contour.frequencies,
+                clarities: contour.clarities,
+            };
+        } finally {
+            contour.free();
+        }
+    }
+
+    /**
+     * Measure the vibrato and steadiness of a stretch of pitch frames, e.g. the last second of
+     * `getPitch` results. Frames with a frequency of `-1` are unvoiced.
+     */
+    async analyzeVibrato(
+        times: Float64Array,
+        frequencies: Float32Array
+    ): Promise<{
+        rate: number;
+        extent: number;
+        regularity: number;
+        deviation: number;
+        steadiness: number;
+    }> {
+        await this.init();
+        if (!(await this.wasmInitialized)) {
+            throw new Error("WASM could not be initialized");
+        }
+
+        const analysis = analyze_vibrato(times, frequencies);
+        try {
+            return {
+                rate: analysis.rate,
+                extent: analysis.extent,
+                regularity: analysis.regularity,
+                deviation: analysis.deviation,
+                steadiness: analysis.steadiness,
+            };
+        } finally {
+            analysis.free();
+        }
+    }
 }
 
 export default Comlink.expose(new PitchWorker());